@@ -0,0 +1,156 @@
+use crate::config::{CONFIG, LOCAL_ATTRIBUTES_SIZE};
+use crate::error::{ERROR_INDEX_OUT_OF_BOUND, ERROR_NOT_ENOUGH_LIQUIDITY, ERROR_OVERFLOW, ERROR_SLIPPAGE};
+use crate::StorageData;
+use serde::Serialize;
+use std::slice::IterMut;
+
+/* Constant product pool between two local resources
+ *
+ * pair is ordered (pair[0] < pair[1]) and the pool id is pair[0] << 8 + pair[1].
+ * The swap fee stays in the reserves so it accrues to the liquidity shares.
+ * The first deposit locks amm_locked_shares for good so that the reserves
+ * can never be drained back to zero.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct Pool {
+    pub pair: [usize; 2],
+    pub reserves: [u64; 2],
+    pub shares: u64,
+}
+
+pub fn pool_id(a: usize, b: usize) -> Result<u64, u32> {
+    if a == b || a >= LOCAL_ATTRIBUTES_SIZE || b >= LOCAL_ATTRIBUTES_SIZE {
+        Err(ERROR_INDEX_OUT_OF_BOUND)
+    } else if a < b {
+        Ok(((a as u64) << 8) + b as u64)
+    } else {
+        Ok(((b as u64) << 8) + a as u64)
+    }
+}
+
+fn sqrt(x: u128) -> u128 {
+    if x < 2 {
+        return x;
+    }
+    let mut r = x;
+    let mut y = (r + 1) / 2;
+    while y < r {
+        r = y;
+        y = (r + x / r) / 2;
+    }
+    r
+}
+
+impl Pool {
+    pub fn new(id: u64) -> Self {
+        Pool {
+            pair: [(id >> 8) as usize, (id & 0xff) as usize],
+            reserves: [0, 0],
+            shares: 0,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        ((self.pair[0] as u64) << 8) + self.pair[1] as u64
+    }
+
+    /// Swap amount_in of resource `from` against the other resource of the pair.
+    pub fn swap(&mut self, from: usize, amount_in: u64, min_out: u64) -> Result<u64, u32> {
+        let (i, o) = if from == self.pair[0] { (0, 1) } else { (1, 0) };
+        if self.reserves[0] == 0 || self.reserves[1] == 0 {
+            return Err(ERROR_NOT_ENOUGH_LIQUIDITY);
+        }
+        let amount_in_with_fee = amount_in as u128 * (10000 - CONFIG.amm_fee() as u128);
+        let amount_out = amount_in_with_fee * self.reserves[o] as u128
+            / (self.reserves[i] as u128 * 10000 + amount_in_with_fee);
+        let amount_out = amount_out as u64;
+        if amount_out == 0 || amount_out < min_out {
+            return Err(ERROR_SLIPPAGE);
+        }
+        self.reserves[i] = self.reserves[i].checked_add(amount_in).ok_or(ERROR_OVERFLOW)?;
+        self.reserves[o] -= amount_out;
+        Ok(amount_out)
+    }
+
+    /// Deposit both resources and mint shares, the pool keeps the unused
+    /// excess of the side that exceeds the current ratio. Returns the shares
+    /// given to the depositor.
+    pub fn add_liquidity(&mut self, amounts: [u64; 2], min_shares: u64) -> Result<u64, u32> {
+        let (minted, locked) = if self.shares == 0 {
+            let minted = sqrt(amounts[0] as u128 * amounts[1] as u128) as u64;
+            let locked = CONFIG.amm_locked_shares();
+            if minted <= locked {
+                return Err(ERROR_NOT_ENOUGH_LIQUIDITY);
+            }
+            (minted, locked)
+        } else {
+            let s0 = amounts[0] as u128 * self.shares as u128 / self.reserves[0] as u128;
+            let s1 = amounts[1] as u128 * self.shares as u128 / self.reserves[1] as u128;
+            (u64::try_from(s0.min(s1)).map_err(|_| ERROR_OVERFLOW)?, 0)
+        };
+        let given = minted - locked;
+        if given == 0 || given < min_shares {
+            return Err(ERROR_SLIPPAGE);
+        }
+        let reserve0 = self.reserves[0].checked_add(amounts[0]).ok_or(ERROR_OVERFLOW)?;
+        let reserve1 = self.reserves[1].checked_add(amounts[1]).ok_or(ERROR_OVERFLOW)?;
+        self.shares = self.shares.checked_add(minted).ok_or(ERROR_OVERFLOW)?;
+        self.reserves = [reserve0, reserve1];
+        Ok(given)
+    }
+
+    pub fn remove_liquidity(&mut self, shares: u64, min_amounts: [u64; 2]) -> Result<[u64; 2], u32> {
+        if shares == 0 || shares > self.shares {
+            return Err(ERROR_NOT_ENOUGH_LIQUIDITY);
+        }
+        let amounts = self
+            .reserves
+            .map(|r| (r as u128 * shares as u128 / self.shares as u128) as u64);
+        if amounts[0] < min_amounts[0] || amounts[1] < min_amounts[1] {
+            return Err(ERROR_SLIPPAGE);
+        }
+        self.reserves[0] -= amounts[0];
+        self.reserves[1] -= amounts[1];
+        self.shares -= shares;
+        Ok(amounts)
+    }
+}
+
+impl StorageData for Pool {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let id = *u64data.next().unwrap();
+        let reserves = [*u64data.next().unwrap(), *u64data.next().unwrap()];
+        let shares = *u64data.next().unwrap();
+        Pool {
+            reserves,
+            shares,
+            ..Pool::new(id)
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.id());
+        data.push(self.reserves[0]);
+        data.push(self.reserves[1]);
+        data.push(self.shares);
+    }
+}
+
+/// Liquidity shares a player holds in a pool.
+#[derive(Clone, Debug, Serialize)]
+pub struct Position {
+    pub pool: u64,
+    pub shares: u64,
+}
+
+impl StorageData for Position {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        Position {
+            pool: *u64data.next().unwrap(),
+            shares: *u64data.next().unwrap(),
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.pool);
+        data.push(self.shares);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::config::{resource_weight, CONFIG};
+use crate::StorageData;
+use serde::Serialize;
+use std::slice::IterMut;
+
+/* A request on the bounty board: deliver amount of a resource for reward
+ * titanium. remaining is the number of players that can still fulfill it,
+ * every player can fulfill each request of a board once.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct BoardBounty {
+    pub resource: usize,
+    pub amount: u64,
+    pub reward: u64,
+    pub remaining: u32,
+}
+
+impl StorageData for BoardBounty {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let info = *u64data.next().unwrap();
+        BoardBounty {
+            resource: (info >> 32) as usize,
+            remaining: (info & 0xffffffff) as u32,
+            amount: *u64data.next().unwrap(),
+            reward: *u64data.next().unwrap(),
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(((self.resource as u64) << 32) + self.remaining as u64);
+        data.push(self.amount);
+        data.push(self.reward);
+    }
+}
+
+/* The board shared by all players, replaced by a new one drawn from the
+ * seed of the first tick of every board period.
+ */
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BountyBoard {
+    pub epoch: u32,
+    pub bounties: Vec<BoardBounty>,
+}
+
+impl BountyBoard {
+    pub fn generate(epoch: u32, seed: &[u64; 4]) -> Self {
+        let eligible = CONFIG
+            .resources()
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.bounty.is_some())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let bounties = (0..CONFIG.board_size())
+            .map(|i| {
+                let b = seed[(i / 8) % 4].to_le_bytes()[i % 8] as u64;
+                let resource = eligible[b as usize % eligible.len()];
+                // the value of a request varies from 100% to 200% of board_value
+                let value = CONFIG.board_value() * (4 + (b >> 4) % 5) / 4;
+                BoardBounty {
+                    resource,
+                    amount: (value / resource_weight(resource)).max(1),
+                    reward: (value / CONFIG.board_value_per_titanium()).max(1),
+                    remaining: CONFIG.board_quantity(),
+                }
+            })
+            .collect();
+        BountyBoard { epoch, bounties }
+    }
+}
+
+impl StorageData for BountyBoard {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let epoch = u64data.next().map_or(0, |x| *x) as u32;
+        let size = u64data.next().map_or(0, |x| *x);
+        BountyBoard {
+            epoch,
+            bounties: (0..size).map(|_| BoardBounty::from_data(u64data)).collect(),
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.epoch as u64);
+        data.push(self.bounties.len() as u64);
+        for b in self.bounties.iter() {
+            b.to_data(data);
+        }
+    }
+}
@@ -1,44 +1,196 @@
-use crate::config::LOCAL_ATTRIBUTES_SIZE;
-use serde::Serialize;
+use crate::config::{CONFIG, LOCAL_ATTRIBUTES_SIZE};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
 use std::slice::IterMut;
 use zkwasm_rest_abi::StorageData;
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug)]
 pub struct Card {
     pub duration: u64,
-    pub attributes: [i8; 8],
+    pub attributes: [i16; LOCAL_ATTRIBUTES_SIZE],
+    pub rarity: u8, // index into CONFIG.card_rarities
+    pub level: u8,
+    pub usage: u16, // completed runs since the last level up
+    pub paid: u64, // titanium paid for the card, bounds its recycle refund
 }
 
 impl Card {
-    fn new(duration: u64, attributes: [i8; LOCAL_ATTRIBUTES_SIZE]) -> Self {
+    // attributes of resources not listed are zero
+    fn new(duration: u64, attrs: &[i16]) -> Self {
+        let mut attributes = [0i16; LOCAL_ATTRIBUTES_SIZE];
+        attributes[..attrs.len()].copy_from_slice(attrs);
         Card {
             duration,
             attributes,
+            rarity: 0,
+            level: 0,
+            usage: 0,
+            paid: 0,
         }
     }
+
+    fn primary_output(&self) -> Option<usize> {
+        let (index, v) = self
+            .attributes
+            .iter()
+            .enumerate()
+            .fold((0, 0), |acc, (i, v)| if *v > acc.1 { (i, *v) } else { acc });
+        if v > 0 { Some(index) } else { None }
+    }
+
+    fn primary_input(&self) -> Option<usize> {
+        let (index, v) = self
+            .attributes
+            .iter()
+            .enumerate()
+            .fold((0, 0), |acc, (i, v)| if *v < acc.1 { (i, *v) } else { acc });
+        if v < 0 { Some(index) } else { None }
+    }
+
+    /// Icon id shared by all frontends: primary output * (n + 1) + primary
+    /// input for n resources, where index n stands for a card without output
+    /// (or input).
+    pub fn icon(&self) -> u64 {
+        let output = self.primary_output().unwrap_or(LOCAL_ATTRIBUTES_SIZE);
+        let input = self.primary_input().unwrap_or(LOCAL_ATTRIBUTES_SIZE);
+        (output * (LOCAL_ATTRIBUTES_SIZE + 1) + input) as u64
+    }
+
+    /* The default cards keep their names, any other card is named after its
+     * primary output and input followed by a code folded from its attributes
+     * and rarity, so the same card always gets the same name.
+     */
+    pub fn name(&self) -> String {
+        if self.rarity == 0 {
+            if let Some(i) = DEFAULT_CARDS.iter().position(|c| c.attributes == self.attributes) {
+                return CARD_NAME[i].to_string();
+            }
+        }
+        let resources = CONFIG.resources();
+        let prefix = self.primary_output().map_or("Void", |i| resources[i].card_prefix);
+        let suffix = self.primary_input().map_or("spring", |i| resources[i].card_suffix);
+        let code = self
+            .attributes
+            .iter()
+            .fold((self.rarity as u64) << 8, |acc, x| acc.rotate_left(5) ^ (*x as u16 as u64));
+        let code = (code ^ (code >> 16) ^ (code >> 32) ^ (code >> 48)) & 0xffff;
+        format!("{}{}-{:04X}", prefix, suffix, code)
+    }
+
+    pub fn energy_cost(&self) -> u64 {
+        CONFIG.card_energy(self.duration)
+    }
+
+    /// Record one completed run of the card, returns true if it levelled up.
+    pub fn record_use(&mut self) -> bool {
+        if self.level >= CONFIG.card_max_level() {
+            return false;
+        }
+        self.usage += 1;
+        if self.usage as u64 >= CONFIG.card_level_usage(self.level) {
+            self.usage = 0;
+            self.level += 1;
+            self.duration = CONFIG.card_level_duration(self.duration);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Serialize for Card {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Card", 8)?;
+        state.serialize_field("duration", &self.duration)?;
+        state.serialize_field("attributes", &self.attributes)?;
+        state.serialize_field("rarity", &self.rarity)?;
+        state.serialize_field("level", &self.level)?;
+        state.serialize_field("usage", &self.usage)?;
+        state.serialize_field("paid", &self.paid)?;
+        state.serialize_field("name", &self.name())?;
+        state.serialize_field("icon", &self.icon())?;
+        state.end()
+    }
 }
 
+// marks the header word, baseline cards start with their duration instead
+const CARD_HEADER: u64 = 1 << 63;
+
 impl StorageData for Card {
+    /* header: CARD_HEADER + number of attributes
+     * card_info: (level << 56) + (rarity << 48) + (usage << 32) + duration
+     *
+     * Cards are written as the header, card_info, four i16 attributes per
+     * u64 and the titanium paid for the card. Baseline cards are the
+     * duration followed by eight i8 attributes in one u64. Attributes of
+     * resources the card does not know about are zero, attributes beyond
+     * the configured resources are dropped.
+     */
     fn from_data(u64data: &mut IterMut<u64>) -> Self {
-        let duration = *u64data.next().unwrap();
-        let attributes = (*u64data.next().unwrap()).to_le_bytes();
+        let first = *u64data.next().unwrap();
+        let mut attributes = [0i16; LOCAL_ATTRIBUTES_SIZE];
+        if first & CARD_HEADER == 0 {
+            let v = (*u64data.next().unwrap()).to_le_bytes();
+            for (a, x) in attributes.iter_mut().zip(v.iter()) {
+                *a = *x as i8 as i16;
+            }
+            return Card {
+                duration: first,
+                attributes,
+                rarity: 0,
+                level: 0,
+                usage: 0,
+                paid: 0,
+            };
+        }
+        let count = (first & 0xffffffff) as usize;
+        let card_info = *u64data.next().unwrap();
+        for c in 0..(count + 3) / 4 {
+            let v = *u64data.next().unwrap();
+            for i in 0..4 {
+                let index = c * 4 + i;
+                if index < count && index < LOCAL_ATTRIBUTES_SIZE {
+                    attributes[index] = ((v >> (16 * i)) & 0xffff) as u16 as i16;
+                }
+            }
+        }
         Card {
-            duration,
-            attributes: attributes.map(|x| x as i8),
+            duration: card_info & 0xffffffff,
+            attributes,
+            rarity: ((card_info >> 48) & 0xff) as u8,
+            level: (card_info >> 56) as u8,
+            usage: ((card_info >> 32) & 0xffff) as u16,
+            paid: *u64data.next().unwrap(),
         }
     }
     fn to_data(&self, data: &mut Vec<u64>) {
-        data.push(self.duration);
-        data.push(u64::from_le_bytes(self.attributes.map(|x| x as u8)));
+        data.push(CARD_HEADER + LOCAL_ATTRIBUTES_SIZE as u64);
+        data.push(
+            ((self.level as u64) << 56)
+                + ((self.rarity as u64) << 48)
+                + ((self.usage as u64) << 32)
+                + self.duration,
+        );
+        for chunk in self.attributes.chunks(4) {
+            let mut v = 0u64;
+            for (i, a) in chunk.iter().enumerate() {
+                v += (*a as u16 as u64) << (16 * i);
+            }
+            data.push(v);
+        }
+        data.push(self.paid);
     }
 }
 
 lazy_static::lazy_static! {
     pub static ref DEFAULT_CARDS: Vec<Card> = vec![
-        Card::new(20, [-10, -10, 20, 0, 0, 0, 0, 0]),
-        Card::new(40, [30, 0, -10, 0, 0, 0, 0, 0]),
-        Card::new(40, [0, 30, -10, 0, 0, 0, 0, 0]),
-        Card::new(40, [10, 0, -30, 0, 20, 0, 0, 0]),
+        Card::new(20, &[-10, -10, 20, 0, 0, 0, 0, 0]),
+        Card::new(40, &[30, 0, -10, 0, 0, 0, 0, 0]),
+        Card::new(40, &[0, 30, -10, 0, 0, 0, 0, 0]),
+        Card::new(40, &[10, 0, -30, 0, 20, 0, 0, 0]),
     ];
     pub static ref CARD_NAME: Vec<&'static str> = vec![
         "Biogen",
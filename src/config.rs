@@ -1,8 +1,67 @@
 use crate::card::Card;
+use crate::cost::{CostCurve, COST_ACTIONS};
+use crate::error::ERROR_OVERFLOW;
 use serde::Serialize;
 
 pub const ENTITY_ATTRIBUTES_SIZE: usize = 4; //level speed efficiency productivity
-pub const LOCAL_ATTRIBUTES_SIZE: usize = 8;
+pub const RARITY_TIERS: usize = 5;
+pub const SEASON_RANKS: usize = 10; // at most 64, see SeasonRecord
+pub const STAKE_LOCKS: usize = 3;
+
+/* resource info
+ *
+ * weight is the value of one unit used to balance cards, initial is the
+ * amount a new player starts with and bounty tells how the resource can be
+ * redeemed for titanium, if at all. The resources are listed in CONFIG, new
+ * resources are appended at the end and LOCAL_ATTRIBUTES_SIZE is raised with
+ * them, at most 16 resources are supported.
+ */
+#[derive(Serialize, Clone, Copy)]
+pub struct Resource {
+    pub name: &'static str,
+    pub weight: u64,
+    pub initial: i64,
+    pub bounty: Option<BountyInfo>,
+    #[serde(skip)]
+    pub card_prefix: &'static str,
+    #[serde(skip)]
+    pub card_suffix: &'static str,
+}
+
+/* bounty info
+ *
+ * the n-th redeem (from 0) in a bounty period takes cost * cost_factor ^ n of
+ * the resource and gives reward * (n + 1) titanium, at most cap redeems are
+ * allowed per period and the count starts over every bounty_reset_period ticks
+ */
+#[derive(Serialize, Clone, Copy)]
+pub struct BountyInfo {
+    pub cost: u64,
+    pub cost_factor: u64,
+    pub reward: u64,
+    pub cap: u8,
+}
+
+impl BountyInfo {
+    pub fn cost(&self, redeemed: u8) -> Result<u64, u32> {
+        let mut cost = self.cost;
+        for _ in 0..redeemed {
+            cost = cost.checked_mul(self.cost_factor).ok_or(ERROR_OVERFLOW)?;
+        }
+        Ok(cost)
+    }
+
+    pub fn reward(&self, redeemed: u8) -> Result<u64, u32> {
+        self.reward
+            .checked_mul(redeemed as u64 + 1)
+            .ok_or(ERROR_OVERFLOW)
+    }
+}
+
+const BOUNTY: BountyInfo = BountyInfo { cost: 20, cost_factor: 2, reward: 4, cap: 16 };
+
+pub const LOCAL_ATTRIBUTES_SIZE: usize = 8; // number of resources in CONFIG
+const _: () = assert!(LOCAL_ATTRIBUTES_SIZE <= 16);
 
 lazy_static::lazy_static! {
     pub static ref ADMIN_PUBKEY: [u64; 4] = {
@@ -17,15 +76,75 @@ lazy_static::lazy_static! {
 pub struct Config {
     version: &'static str,
     entity_attributes: [&'static str; ENTITY_ATTRIBUTES_SIZE],
-    local_attributes: [&'static str; LOCAL_ATTRIBUTES_SIZE],
-    bounty_cost_base: u64, // , 2, 4, 8, ....  index < 2: 0, index >=2:  cost_exp ^(level-2)
-    bounty_reward_base: u64,
+    resources: [Resource; LOCAL_ATTRIBUTES_SIZE],
+    titanium: usize, // index of the resource used as currency
+    bounty_reset_period: u64, // ticks after which the redeem counts start over
+    card_rarities: [CardRarity; RARITY_TIERS],
+    card_level_usage: u64, // runs needed to reach level n + 1 is card_level_usage * (n + 1)
+    card_max_level: u8,
+    card_level_discount: u64, // duration reduction in percent per level
+    market_fee: u64, // percent of the listing price paid to the treasury
+    market_listing_duration: u64, // ticks before a listing expires
+    craft_premium: u64, // extra titanium paid on top of the action cost to craft a card
+    reroll_cost_base: u64, // the n-th reroll in a period costs reroll_cost_base * 2^n titanium
+    reroll_reset_period: u64, // ticks after which the reroll count starts over
+    card_attribute_bound: i64, // generated attributes are clamped to [-bound, bound]
+    card_weight_offset: i64,
+    card_duration_factor: i64,
+    card_duration_base: i64,
+    amm_fee: u64, // swap fee in basis points, kept by the pool
+    amm_locked_shares: u64, // shares of the first deposit of a pool that nobody can withdraw
+    ticks_per_day: u64,
+    transfer_daily_cap: u64, // weighted value a player can send per day, 0 for no cap
+    transfer_fee: u64, // percent of a transfer that is not delivered
+    energy_regen_ticks: u64, // ticks to regenerate one energy per player level
+    energy_cap_base: u64, // max energy is energy_cap_base + energy_cap_per_level * level
+    energy_cap_per_level: u64,
+    energy_collect_cost: u64, // titanium charged by CollectEnergy
+    card_energy_duration: u64, // a card costs 1 + duration / card_energy_duration energy per run
+    object_class_energy: [u64; ENTITY_ATTRIBUTES_SIZE], // extra energy per run by object class
+    cost_energy_bonus: u64, // energy granted with every paid action
+    energy_emission: u64, // energy added to the global supply every tick
+    energy_supply_cap: u64, // emission stops once the supply reaches this
+    cost_curves: [CostCurve; COST_ACTIONS], // indexed by the COST_* actions
+    board_period: u64, // ticks a bounty board stays up
+    board_size: usize, // requests per board, at most 32
+    board_value: u64, // weighted value of a request before its random bonus
+    board_value_per_titanium: u64,
+    board_quantity: u32, // players that can fulfill each request
+    season_duration: u64, // ticks per season
+    season_rewards: [u64; SEASON_RANKS], // reward claimable by each rank of a season
+    season_keep_percent: i64, // resources (but titanium) kept into a new season
+    leaderboard_size: usize,
+    stake_locks: [StakeLock; STAKE_LOCKS],
+    stake_max_positions: usize,
+    stake_fee_share: u64, // percent of the titanium paid by pay_cost that funds stake rewards
 }
 
-/* bounty info
+/* stake lock info
  *
- * 20 * bounty_cost_base ^ redeem_info can used to replace bounty_reward_base * (redeem_info + 1) resource
+ * titanium staked with a lock can not be unstaked for ticks ticks and earns
+ * rate basis points per day
  */
+#[derive(Serialize, Clone, Copy)]
+pub struct StakeLock {
+    pub ticks: u64,
+    pub rate: u64,
+}
+
+/* rarity info
+ *
+ * chance is out of 256 and the chances of all tiers sum up to 256,
+ * output_bonus scales the produced amount and duration_discount shortens
+ * the duration, both in percent
+ */
+#[derive(Serialize, Clone)]
+pub struct CardRarity {
+    name: &'static str,
+    chance: u64,
+    output_bonus: u64,
+    duration_discount: u64,
+}
 
 pub fn default_entities(index: usize) -> [i64; ENTITY_ATTRIBUTES_SIZE] {
     if index < 2 {
@@ -40,25 +159,55 @@ pub fn default_entities(index: usize) -> [i64; ENTITY_ATTRIBUTES_SIZE] {
 }
 
 pub fn default_local() -> [i64; LOCAL_ATTRIBUTES_SIZE] {
-    [30, 30, 0, 0, 2, 0, 0, 0]
+    CONFIG.resources.map(|r| r.initial)
 }
 
-const LOCAL_RESOURCE_WEIGHT: [u64; LOCAL_ATTRIBUTES_SIZE] = [1, 1, 2, 4, 8, 16, 32, 128];
+pub fn resource_weight(index: usize) -> u64 {
+    CONFIG.resources[index].weight
+}
 pub const COST_INCREASE_ROUND: u16 = 5;
 pub const INITIAL_ENERGY: u16 = 256;
+pub const RECYCLE_REFUND_DIVISOR: u64 = 2; // recycling refunds 1/2 of the card weight in titanium, at most what was paid
 
 pub fn random_modifier(current_resource: [i64; LOCAL_ATTRIBUTES_SIZE], rand: u64) -> Card {
     let rand_bytes = rand.to_le_bytes().map(|x| x as u64);
-    let output1 = rand_bytes[0] & 0x7; // select two target result
-    let output2 = (rand_bytes[0] >> 4) & 0x7; // select two target result
+    let output1 = (rand_bytes[0] & 0xf) % LOCAL_ATTRIBUTES_SIZE as u64; // select two target result
+    let output2 = ((rand_bytes[0] >> 4) & 0xf) % LOCAL_ATTRIBUTES_SIZE as u64; // select two target result
+    let price = |output: u64| output * resource_weight(output as usize);
+    build_modifier(current_resource, rand_bytes, output1, output2, price)
+}
+
+/// Same as random_modifier except that the two outputs are chosen by the
+/// player, naming the same resource twice puts both outputs on it. Outputs
+/// are priced by their resource weight alone so that no choice of outputs
+/// makes the inputs free.
+pub fn crafted_modifier(
+    current_resource: [i64; LOCAL_ATTRIBUTES_SIZE],
+    rand: u64,
+    outputs: [usize; 2],
+) -> Card {
+    let rand_bytes = rand.to_le_bytes().map(|x| x as u64);
+    let price = |output: u64| resource_weight(output as usize);
+    build_modifier(current_resource, rand_bytes, outputs[0] as u64, outputs[1] as u64, price)
+}
+
+// price is the weight of one unit of an output, it sets the input costs
+fn build_modifier(
+    current_resource: [i64; LOCAL_ATTRIBUTES_SIZE],
+    rand_bytes: [u64; 8],
+    output1: u64,
+    output2: u64,
+    price: impl Fn(u64) -> u64,
+) -> Card {
     let cost1 = (rand_bytes[1] & 0x3) as u64; // select two target number
     let cost2 = ((rand_bytes[1] >> 4) & 0x3) as u64; // select two target number
-    let mut weight = output1 * cost1 * LOCAL_RESOURCE_WEIGHT[output1 as usize];
-    weight += output2 * cost2 * LOCAL_RESOURCE_WEIGHT[output2 as usize];
-    let input1 = (rand_bytes[2] & 0x7) as usize;
-    let input2 = ((rand_bytes[2] >> 4) & 0x7) as usize;
-    let input3 = (rand_bytes[3] & 0x7) as usize;
-    let input4 = ((rand_bytes[3] >> 4) & 0x7) as usize;
+    let rarity = CONFIG.pick_rarity(rand_bytes[4]);
+    let mut weight = cost1 * price(output1);
+    weight += cost2 * price(output2);
+    let input1 = (rand_bytes[2] & 0xf) as usize % LOCAL_ATTRIBUTES_SIZE;
+    let input2 = ((rand_bytes[2] >> 4) & 0xf) as usize % LOCAL_ATTRIBUTES_SIZE;
+    let input3 = (rand_bytes[3] & 0xf) as usize % LOCAL_ATTRIBUTES_SIZE;
+    let input4 = ((rand_bytes[3] >> 4) & 0xf) as usize % LOCAL_ATTRIBUTES_SIZE;
     let mut inputs = [input1, input2, input3, input4];
     inputs.sort();
 
@@ -67,47 +216,159 @@ pub fn random_modifier(current_resource: [i64; LOCAL_ATTRIBUTES_SIZE], rand: u64
         + current_resource[input3]
         + current_resource[input4];
     let cost = inputs.map(|x| {
-        weight * (current_resource[x] as u64) / (LOCAL_RESOURCE_WEIGHT[x] * (distribute as u64) + 1)
+        weight * (current_resource[x] as u64) / (resource_weight(x) * (distribute as u64) + 1)
     });
-    let mut attrs = [0i64; 8];
+    let mut attrs = [0i64; LOCAL_ATTRIBUTES_SIZE];
     attrs[inputs[0] as usize] -= cost[0] as i64;
     attrs[inputs[1] as usize] -= cost[1] as i64;
     attrs[inputs[2] as usize] -= cost[2] as i64;
     attrs[inputs[3] as usize] -= cost[3] as i64;
     attrs[output1 as usize] += cost1 as i64;
     attrs[output2 as usize] += cost2 as i64;
-    let attrs = attrs.map(|x| {
-        if x > 64 {
-            64 as i8
-        } else if x < -64 {
-            -64 as i8
-        } else {
-            x as i8
+    let attrs = attrs.map(clamp_attribute);
+    // the duration follows the plain outputs, the rarity bonus comes on top
+    let duration = CONFIG.discount_duration(card_duration(&attrs), rarity);
+    let mut boosted = attrs;
+    for (i, v) in attrs.iter().enumerate() {
+        if *v > 0 && (i as u64 == output1 || i as u64 == output2) {
+            boosted[i] = clamp_attribute(CONFIG.boost_output(*v as i64, rarity));
         }
-    });
+    }
+    Card {
+        duration,
+        attributes: boosted,
+        rarity,
+        level: 0,
+        usage: 0,
+        paid: 0,
+    }
+}
+
+pub fn clamp_attribute(x: i64) -> i16 {
+    let bound = CONFIG.card_attribute_bound;
+    if x > bound {
+        bound as i16
+    } else if x < -bound {
+        -bound as i16
+    } else {
+        x as i16
+    }
+}
 
+/* The duration of a card grows with the weighted value it produces:
+ * weight = sum(attr * resource weight) + card_weight_offset
+ * duration = weight * card_duration_factor + card_duration_base
+ */
+pub fn card_weight(attrs: &[i16; LOCAL_ATTRIBUTES_SIZE]) -> i64 {
     let mut weight = 0i64;
-    for i in 0..LOCAL_ATTRIBUTES_SIZE {
-        weight = weight + (attrs[i] as i64) * (LOCAL_RESOURCE_WEIGHT[i] as i64)
+    for (i, a) in attrs.iter().enumerate() {
+        weight += (*a as i64) * (resource_weight(i) as i64);
     }
-    weight += 5;
-    zkwasm_rust_sdk::dbg!("random modifier weight {}\n", weight);
+    weight + CONFIG.card_weight_offset
+}
 
-    let duration = if weight < 0 { 15 } else { weight * 40 + 15 };
+pub fn card_duration(attrs: &[i16; LOCAL_ATTRIBUTES_SIZE]) -> u64 {
+    let weight = card_weight(attrs);
+    zkwasm_rust_sdk::dbg!("card weight {}\n", weight);
 
+    let duration = if weight < 0 {
+        CONFIG.card_duration_base
+    } else {
+        weight * CONFIG.card_duration_factor + CONFIG.card_duration_base
+    };
+    duration as u64
+}
+
+/// Merge two cards by summing their attributes, the duration follows the
+/// merged attributes so that a stronger card also takes longer to run.
+/// The fused card keeps the higher rarity of the two.
+pub fn fuse_modifier(a: &Card, b: &Card) -> Card {
+    let mut attrs = [0i16; LOCAL_ATTRIBUTES_SIZE];
+    for (x, (va, vb)) in attrs.iter_mut().zip(a.attributes.iter().zip(b.attributes.iter())) {
+        *x = clamp_attribute(*va as i64 + *vb as i64);
+    }
+    let rarity = a.rarity.max(b.rarity);
     Card {
-        duration: duration as u64,
+        duration: CONFIG.discount_duration(card_duration(&attrs), rarity),
         attributes: attrs,
+        rarity,
+        level: 0,
+        usage: 0,
+        paid: a.paid.saturating_add(b.paid),
     }
 }
 
 lazy_static::lazy_static! {
     pub static ref CONFIG: Config = Config {
         version: "1.2",
-        bounty_cost_base : 2,
-        bounty_reward_base: 4,
+        bounty_reset_period: 17280,
         entity_attributes: ["Level", "Speed", "Efficiency", "Producitivity"],
-        local_attributes: ["Engery Crystal", "Instellar Mineral", "Biomass", "Quantum Foam", "Necrodermis", "Alien Floral", "Spice Melange", "Titanium"],
+        resources: [
+            Resource { name: "Engery Crystal", weight: 1, initial: 30, bounty: Some(BOUNTY), card_prefix: "Crysta", card_suffix: "forge" },
+            Resource { name: "Instellar Mineral", weight: 1, initial: 30, bounty: Some(BOUNTY), card_prefix: "Astro", card_suffix: "mine" },
+            Resource { name: "Biomass", weight: 2, initial: 0, bounty: Some(BOUNTY), card_prefix: "Bio", card_suffix: "gen" },
+            Resource { name: "Quantum Foam", weight: 4, initial: 0, bounty: Some(BOUNTY), card_prefix: "Quanta", card_suffix: "flux" },
+            Resource { name: "Necrodermis", weight: 8, initial: 2, bounty: Some(BOUNTY), card_prefix: "Necro", card_suffix: "weave" },
+            Resource { name: "Alien Floral", weight: 16, initial: 0, bounty: Some(BOUNTY), card_prefix: "Flora", card_suffix: "bloom" },
+            Resource { name: "Spice Melange", weight: 32, initial: 0, bounty: Some(BOUNTY), card_prefix: "Spice", card_suffix: "drift" },
+            Resource { name: "Titanium", weight: 128, initial: 0, bounty: None, card_prefix: "Titan", card_suffix: "smelt" },
+        ],
+        titanium: 7,
+        card_rarities: [
+            CardRarity { name: "Common", chance: 160, output_bonus: 0, duration_discount: 0 },
+            CardRarity { name: "Uncommon", chance: 60, output_bonus: 25, duration_discount: 5 },
+            CardRarity { name: "Rare", chance: 24, output_bonus: 50, duration_discount: 10 },
+            CardRarity { name: "Epic", chance: 10, output_bonus: 75, duration_discount: 20 },
+            CardRarity { name: "Legendary", chance: 2, output_bonus: 100, duration_discount: 30 },
+        ],
+        card_level_usage: 10,
+        card_max_level: 5,
+        card_level_discount: 10,
+        market_fee: 5,
+        market_listing_duration: 17280,
+        craft_premium: 10,
+        reroll_cost_base: 2,
+        reroll_reset_period: 17280,
+        card_attribute_bound: 512,
+        card_weight_offset: 5,
+        card_duration_factor: 40,
+        card_duration_base: 15,
+        amm_fee: 30,
+        amm_locked_shares: 10,
+        ticks_per_day: 17280,
+        transfer_daily_cap: 0,
+        transfer_fee: 2,
+        energy_regen_ticks: 100,
+        energy_cap_base: 256,
+        energy_cap_per_level: 16,
+        energy_collect_cost: 1,
+        card_energy_duration: 200,
+        object_class_energy: [0, 1, 1, 2],
+        cost_energy_bonus: 20,
+        energy_emission: 1,
+        energy_supply_cap: 100000,
+        cost_curves: [
+            CostCurve::Exponential { round: COST_INCREASE_ROUND as u64, cap: 1 << 20 },
+            CostCurve::Decaying { base: 0, step: 1, decay_ticks: 720 },
+            CostCurve::Exponential { round: COST_INCREASE_ROUND as u64, cap: 1 << 20 },
+            CostCurve::Linear { base: 0, step: 1 },
+        ],
+        board_period: 17280,
+        board_size: 4,
+        board_value: 1000,
+        board_value_per_titanium: 25,
+        board_quantity: 10,
+        season_duration: 120960,
+        season_rewards: [1000, 600, 400, 300, 250, 200, 150, 100, 75, 50],
+        season_keep_percent: 50,
+        leaderboard_size: 20,
+        stake_locks: [
+            StakeLock { ticks: 17280, rate: 10 },
+            StakeLock { ticks: 120960, rate: 20 },
+            StakeLock { ticks: 518400, rate: 40 },
+        ],
+        stake_max_positions: 8,
+        stake_fee_share: 50,
     };
 }
 
@@ -119,15 +380,220 @@ impl Config {
         true
     }
 
-    pub fn get_bounty_cost(&self, redeem_info: u64) -> u64 {
-        let mut cost = 20;
-        for _ in 0..redeem_info {
-            cost = self.bounty_cost_base * cost
+    pub fn resources(&self) -> &[Resource] {
+        &self.resources
+    }
+
+    pub fn resource(&self, index: usize) -> Option<&Resource> {
+        self.resources.get(index)
+    }
+
+    pub fn titanium(&self) -> usize {
+        self.titanium
+    }
+
+    pub fn pick_rarity(&self, roll: u64) -> u8 {
+        let mut acc = 0;
+        for (i, tier) in self.card_rarities.iter().enumerate() {
+            acc += tier.chance;
+            if roll < acc {
+                return i as u8;
+            }
         }
-        return cost;
+        0
+    }
+
+    /// Scale a produced amount by the output bonus of a rarity, rounded to
+    /// the nearest unit.
+    pub fn boost_output(&self, amount: i64, rarity: u8) -> i64 {
+        let bonus = self.card_rarities[rarity as usize].output_bonus as i64;
+        (amount * (100 + bonus) + 50) / 100
+    }
+
+    pub fn discount_duration(&self, duration: u64, rarity: u8) -> u64 {
+        let discount = self.card_rarities[rarity as usize].duration_discount;
+        duration * (100 - discount) / 100
+    }
+
+    pub fn card_max_level(&self) -> u8 {
+        self.card_max_level
+    }
+
+    pub fn card_level_usage(&self, level: u8) -> u64 {
+        self.card_level_usage * (level as u64 + 1)
+    }
+
+    pub fn card_level_duration(&self, duration: u64) -> u64 {
+        let duration = duration * (100 - self.card_level_discount) / 100;
+        if duration == 0 { 1 } else { duration }
+    }
+
+    pub fn market_fee(&self, price: u64) -> u64 {
+        (price as u128 * self.market_fee as u128 / 100) as u64
+    }
+
+    pub fn market_listing_duration(&self) -> u64 {
+        self.market_listing_duration
+    }
+
+    pub fn craft_premium(&self) -> u64 {
+        self.craft_premium
+    }
+
+    pub fn reroll_epoch(&self, counter: u64) -> u32 {
+        (counter / self.reroll_reset_period) as u32
+    }
+
+    pub fn reroll_cost(&self, reroll_count: u16) -> u64 {
+        self.reroll_cost_base << reroll_count.min(16)
+    }
+
+    pub fn amm_fee(&self) -> u64 {
+        self.amm_fee
+    }
+
+    pub fn amm_locked_shares(&self) -> u64 {
+        self.amm_locked_shares
+    }
+
+    pub fn ticks_per_day(&self) -> u64 {
+        self.ticks_per_day
+    }
+
+    pub fn day(&self, counter: u64) -> u32 {
+        (counter / self.ticks_per_day) as u32
+    }
+
+    pub fn transfer_daily_cap(&self) -> u64 {
+        self.transfer_daily_cap
+    }
+
+    pub fn transfer_fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.transfer_fee as u128 / 100) as u64
+    }
+
+    pub fn energy_regen_ticks(&self) -> u64 {
+        self.energy_regen_ticks
+    }
+
+    pub fn max_energy(&self, level: u16) -> u64 {
+        (self.energy_cap_base + self.energy_cap_per_level * level as u64).min(0xffff)
+    }
+
+    pub fn energy_collect_cost(&self) -> u64 {
+        self.energy_collect_cost
+    }
+
+    pub fn card_energy(&self, duration: u64) -> u64 {
+        1 + duration / self.card_energy_duration
+    }
+
+    pub fn object_class_energy(&self, class: usize) -> u64 {
+        self.object_class_energy[class]
+    }
+
+    pub fn cost_energy_bonus(&self) -> u64 {
+        self.cost_energy_bonus
+    }
+
+    pub fn energy_emission(&self) -> u64 {
+        self.energy_emission
+    }
+
+    pub fn energy_supply_cap(&self) -> u64 {
+        self.energy_supply_cap
+    }
+
+    pub fn cost_curve(&self, action: usize) -> &CostCurve {
+        &self.cost_curves[action]
+    }
+
+    pub fn bounty_reset_period(&self) -> u64 {
+        self.bounty_reset_period
+    }
+
+    pub fn bounty_epoch(&self, counter: u64) -> u32 {
+        (counter / self.bounty_reset_period) as u32
+    }
+
+    pub fn board_epoch(&self, counter: u64) -> u32 {
+        (counter / self.board_period) as u32
+    }
+
+    pub fn board_size(&self) -> usize {
+        self.board_size.min(32)
+    }
+
+    pub fn board_value(&self) -> u64 {
+        self.board_value
+    }
+
+    pub fn board_value_per_titanium(&self) -> u64 {
+        self.board_value_per_titanium
+    }
+
+    pub fn board_quantity(&self) -> u32 {
+        self.board_quantity
+    }
+
+    pub fn season(&self, counter: u64) -> u32 {
+        (counter / self.season_duration) as u32
+    }
+
+    pub fn season_rewards(&self) -> &[u64] {
+        &self.season_rewards
+    }
+
+    pub fn season_keep_percent(&self) -> i64 {
+        self.season_keep_percent
+    }
+
+    pub fn leaderboard_size(&self) -> usize {
+        self.leaderboard_size
+    }
+
+    pub fn stake_lock(&self, lock: usize) -> &StakeLock {
+        &self.stake_locks[lock]
+    }
+
+    pub fn stake_max_positions(&self) -> usize {
+        self.stake_max_positions
+    }
+
+    pub fn stake_fee(&self, cost: u64) -> u64 {
+        (cost as u128 * self.stake_fee_share as u128 / 100) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ERROR_OVERFLOW;
+
+    #[test]
+    fn bounty_cost_bounds() {
+        assert_eq!(BOUNTY.cost(0), Ok(20));
+        assert_eq!(BOUNTY.cost(1), Ok(40));
+        assert_eq!(BOUNTY.cost(BOUNTY.cap - 1), Ok(20 << (BOUNTY.cap - 1)));
+        assert_eq!(BOUNTY.cost(u8::MAX), Err(ERROR_OVERFLOW));
+        let unit = BountyInfo { cost: 1, cost_factor: 2, reward: 1, cap: u8::MAX };
+        assert_eq!(unit.cost(63), Ok(1 << 63));
+        assert_eq!(unit.cost(64), Err(ERROR_OVERFLOW));
+        let flat = BountyInfo { cost: u64::MAX, cost_factor: 1, reward: 1, cap: u8::MAX };
+        assert_eq!(flat.cost(u8::MAX), Ok(u64::MAX));
+        let free = BountyInfo { cost: 0, cost_factor: 2, reward: 1, cap: u8::MAX };
+        assert_eq!(free.cost(u8::MAX), Ok(0));
     }
 
-    pub fn get_bounty_reward(&self, redeem_info: u64) -> u64 {
-        return self.bounty_reward_base * (redeem_info + 1);
+    #[test]
+    fn bounty_reward_bounds() {
+        assert_eq!(BOUNTY.reward(0), Ok(4));
+        assert_eq!(BOUNTY.reward(BOUNTY.cap - 1), Ok(4 * BOUNTY.cap as u64));
+        assert_eq!(BOUNTY.reward(u8::MAX), Ok(4 * 256));
+        let large = BountyInfo { cost: 1, cost_factor: 1, reward: u64::MAX, cap: u8::MAX };
+        assert_eq!(large.reward(0), Ok(u64::MAX));
+        assert_eq!(large.reward(1), Err(ERROR_OVERFLOW));
+        let half = BountyInfo { cost: 1, cost_factor: 1, reward: u64::MAX / 256, cap: u8::MAX };
+        assert_eq!(half.reward(u8::MAX), Ok(u64::MAX / 256 * 256));
     }
 }
@@ -0,0 +1,143 @@
+use crate::config::COST_INCREASE_ROUND;
+use crate::error::ERROR_OVERFLOW;
+use crate::StorageData;
+use serde::Serialize;
+use std::slice::IterMut;
+
+/* Actions priced by pay_cost, each has its own counter and curve */
+pub const COST_INSTALL_OBJECT: usize = 0;
+pub const COST_RESTART_OBJECT: usize = 1;
+pub const COST_UPGRADE_OBJECT: usize = 2;
+pub const COST_CARD: usize = 3; // install, craft and fuse cards
+pub const COST_ACTIONS: usize = 4;
+
+/* cost curve
+ *
+ * Linear: base + step * paid
+ * Exponential: 0 for the first round actions, then 1, 2, 4, ... every round actions, up to cap
+ * Decaying: linear, but one paid action is forgotten every decay_ticks ticks
+ */
+#[derive(Serialize, Clone)]
+#[serde(tag = "curve")]
+pub enum CostCurve {
+    Linear { base: u64, step: u64 },
+    Exponential { round: u64, cap: u64 },
+    Decaying { base: u64, step: u64, decay_ticks: u64 },
+}
+
+impl CostCurve {
+    pub fn cost(&self, paid: u64) -> Result<u64, u32> {
+        match self {
+            CostCurve::Linear { base, step } | CostCurve::Decaying { base, step, .. } => step
+                .checked_mul(paid)
+                .and_then(|x| x.checked_add(*base))
+                .ok_or(ERROR_OVERFLOW),
+            CostCurve::Exponential { round, cap } => {
+                let doublings = paid / round;
+                if doublings == 0 {
+                    Ok(0)
+                } else {
+                    let cost = u32::try_from(doublings - 1)
+                        .ok()
+                        .and_then(|x| 1u64.checked_shl(x));
+                    Ok(cost.map_or(*cap, |x| x.min(*cap)))
+                }
+            }
+        }
+    }
+
+    /// Number of paid actions still counted after elapsed ticks.
+    pub fn decay(&self, paid: u32, elapsed: u64) -> u32 {
+        match self {
+            CostCurve::Decaying { decay_ticks, .. } => {
+                let forgotten = (elapsed / decay_ticks).min(u32::MAX as u64) as u32;
+                paid.saturating_sub(forgotten)
+            }
+            _ => paid,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CostCounter {
+    pub paid: u32,
+    pub last_paid: u32, // counter of the last paid action
+}
+
+impl CostCounter {
+    /// Rebuild the paid count from the single counter used before the cost
+    /// curves: cost_info counted down from COST_INCREASE_ROUND and
+    /// current_cost doubled every time it reached zero.
+    pub fn from_legacy(cost_info: u16, current_cost: u32) -> Self {
+        let round = COST_INCREASE_ROUND as u32;
+        let rounds = if current_cost == 0 { 0 } else { 32 - current_cost.leading_zeros() };
+        CostCounter {
+            paid: rounds * round + round.saturating_sub(cost_info as u32),
+            last_paid: 0,
+        }
+    }
+}
+
+impl StorageData for CostCounter {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let v = *u64data.next().unwrap();
+        CostCounter {
+            paid: (v >> 32) as u32,
+            last_paid: (v & 0xffffffff) as u32,
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(((self.paid as u64) << 32) + self.last_paid as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_cost_bounds() {
+        let curve = CostCurve::Linear { base: 3, step: 2 };
+        assert_eq!(curve.cost(0), Ok(3));
+        assert_eq!(curve.cost((u64::MAX - 3) / 2), Ok(u64::MAX));
+        assert_eq!(curve.cost((u64::MAX - 3) / 2 + 1), Err(ERROR_OVERFLOW));
+        assert_eq!(curve.cost(u64::MAX), Err(ERROR_OVERFLOW));
+        let free = CostCurve::Linear { base: 0, step: 1 };
+        assert_eq!(free.cost(u64::MAX), Ok(u64::MAX));
+        let capped = CostCurve::Linear { base: 1, step: 1 };
+        assert_eq!(capped.cost(u64::MAX - 1), Ok(u64::MAX));
+        assert_eq!(capped.cost(u64::MAX), Err(ERROR_OVERFLOW));
+    }
+
+    #[test]
+    fn exponential_cost_bounds() {
+        let curve = CostCurve::Exponential { round: 5, cap: 1 << 20 };
+        assert_eq!(curve.cost(0), Ok(0));
+        assert_eq!(curve.cost(4), Ok(0));
+        assert_eq!(curve.cost(5), Ok(1));
+        assert_eq!(curve.cost(9), Ok(1));
+        assert_eq!(curve.cost(10), Ok(2));
+        assert_eq!(curve.cost(5 * 20 + 4), Ok(1 << 19));
+        assert_eq!(curve.cost(5 * 21), Ok(1 << 20));
+        assert_eq!(curve.cost(5 * 65), Ok(1 << 20));
+        assert_eq!(curve.cost(5 * ((1 << 32) + 1)), Ok(1 << 20));
+        assert_eq!(curve.cost(u64::MAX), Ok(1 << 20));
+        let uncapped = CostCurve::Exponential { round: 1, cap: u64::MAX };
+        assert_eq!(uncapped.cost(64), Ok(1 << 63));
+        assert_eq!(uncapped.cost(65), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn decaying_cost_bounds() {
+        let curve = CostCurve::Decaying { base: 0, step: 1, decay_ticks: 720 };
+        assert_eq!(curve.cost(u64::MAX), Ok(u64::MAX));
+        assert_eq!(curve.decay(5, 719), 5);
+        assert_eq!(curve.decay(5, 720), 4);
+        assert_eq!(curve.decay(5, 720 * 5), 0);
+        assert_eq!(curve.decay(5, u64::MAX), 0);
+        assert_eq!(curve.decay(u32::MAX, 0), u32::MAX);
+        assert_eq!(curve.decay(u32::MAX, u64::MAX), 0);
+        let linear = CostCurve::Linear { base: 0, step: 1 };
+        assert_eq!(linear.decay(u32::MAX, u64::MAX), u32::MAX);
+    }
+}
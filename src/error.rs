@@ -3,3 +3,23 @@ pub const ERROR_PLAYER_NOT_EXIST: u32 = 2;
 pub const ERROR_NOT_ENOUGH_BALANCE: u32 = 3;
 pub const ERROR_INDEX_OUT_OF_BOUND: u32 = 4;
 pub const ERROR_NOT_ENOUGH_RESOURCE: u32 = 5;
+pub const ERROR_CARD_IN_USE: u32 = 6;
+pub const ERROR_INVALID_FUSION: u32 = 7;
+pub const ERROR_OFFER_NOT_EXIST: u32 = 8;
+pub const ERROR_OFFER_FORBIDDEN: u32 = 9;
+pub const ERROR_LISTING_NOT_EXIST: u32 = 10;
+pub const ERROR_BOUNTY_NOT_ELIGIBLE: u32 = 11;
+pub const ERROR_SLIPPAGE: u32 = 12;
+pub const ERROR_POOL_NOT_EXIST: u32 = 13;
+pub const ERROR_NOT_ENOUGH_LIQUIDITY: u32 = 14;
+pub const ERROR_INVALID_TRANSFER: u32 = 15;
+pub const ERROR_TRANSFER_LIMIT: u32 = 16;
+pub const ERROR_OVERFLOW: u32 = 17;
+pub const ERROR_BOUNTY_LIMIT: u32 = 18;
+pub const ERROR_BOUNTY_CLAIMED: u32 = 19;
+pub const ERROR_NO_SEASON_REWARD: u32 = 20;
+pub const ERROR_STAKE_LOCKED: u32 = 21;
+pub const ERROR_TOO_MANY_STAKES: u32 = 22;
+pub const ERROR_LEDGER_SEEDED: u32 = 23;
+pub const ERROR_INVALID_PRICE: u32 = 24;
+pub const ERROR_INVALID_CRAFT: u32 = 25;
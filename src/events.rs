@@ -1,10 +1,28 @@
-use crate::player::AutomataPlayer;
+use crate::config::CONFIG;
+use crate::leaderboard::{Leaderboard, LEADERBOARD_LEVEL, LEADERBOARD_TITANIUM};
+use crate::market::CardListing;
+use crate::player::{AutomataPlayer, Pending};
 use core::slice::IterMut;
+use std::cell::RefCell;
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_convention::EventHandler;
 
+pub const EVENT_OBJECT: u8 = 0;
+pub const EVENT_LISTING_EXPIRE: u8 = 1; // owner[0] holds the listing id, object_index is too narrow
+
+/* What the players handled in a tick owe to State, the queue holds STATE
+ * while the events run so Tick settles it once the queue is done.
+ */
+pub struct SafePending(pub RefCell<Pending>);
+unsafe impl Sync for SafePending {}
+
+lazy_static::lazy_static! {
+    pub static ref TICK_PENDING: SafePending = SafePending(RefCell::new(Pending::default()));
+}
+
 #[derive(Clone)]
 pub struct Event {
+    pub kind: u8,
     pub owner: [u64; 2],
     pub object_index: usize,
     pub delta: usize,
@@ -14,19 +32,37 @@ impl StorageData for Event {
     fn to_data(&self, buf: &mut Vec<u64>) {
         buf.push(self.owner[0]);
         buf.push(self.owner[1]);
-        buf.push(((self.object_index as u64) << 32) | self.delta as u64);
+        buf.push(
+            ((self.kind as u64) << 56)
+                | ((self.object_index as u64) << 32)
+                | self.delta as u64,
+        );
     }
     fn from_data(u64data: &mut IterMut<u64>) -> Event {
         let owner = [*u64data.next().unwrap(), *u64data.next().unwrap()];
         let f = *u64data.next().unwrap();
         Event {
+            kind: (f >> 56) as u8,
             owner,
-            object_index: (f >> 32) as usize,
+            object_index: ((f >> 32) & 0xffffff) as usize,
             delta: (f & 0xffffffff) as usize,
         }
     }
 }
 
+impl Event {
+    fn expire_listing(&self) {
+        let listing_id = self.owner[0];
+        // the listing might have been bought or cancelled already
+        if let Some(listing) = CardListing::get(listing_id) {
+            let mut player = AutomataPlayer::get_from_pid(&listing.seller).unwrap();
+            player.data.cards.push(listing.card);
+            CardListing::close(listing_id);
+            player.store();
+        }
+    }
+}
+
 impl EventHandler for Event {
     fn u64size() -> usize {
         3
@@ -38,21 +74,30 @@ impl EventHandler for Event {
         self.delta -= d;
     }
     fn handle(&mut self, counter: u64) -> Option<Self> {
+        if self.kind == EVENT_LISTING_EXPIRE {
+            self.expire_listing();
+            return None;
+        }
         let owner_id = self.owner;
         let object_index = self.object_index;
         let mut player = AutomataPlayer::get_from_pid(&owner_id).unwrap();
+        player.data.enter_season(CONFIG.season(counter));
+        let level = player.data.level;
+        let titanium_produced = player.data.stats.titanium_produced;
         let m = if player.data.energy == 0 {
             player.data.objects.get_mut(object_index).unwrap().halt();
+            player.data.stats.halts = player.data.stats.halts.saturating_add(1);
             None
         } else {
             player.data.apply_object_card(object_index, counter)
         };
         let event = if let Some(delta) = m {
+            // energy is charged by apply_object_card for every completed card
             if player.data.objects[object_index].get_modifier_index() == 0 {
-                player.data.energy -= 1;
                 player.data.inc_exp();
             }
             Some(Event {
+                kind: EVENT_OBJECT,
                 owner: owner_id,
                 object_index,
                 delta,
@@ -60,7 +105,15 @@ impl EventHandler for Event {
         } else {
             None
         };
+        TICK_PENDING.0.borrow_mut().merge(std::mem::take(&mut player.data.pending));
         player.store();
+        if player.data.level != level {
+            Leaderboard::record(LEADERBOARD_LEVEL, &owner_id, player.data.level as u64);
+        }
+        if player.data.stats.titanium_produced != titanium_produced {
+            let score = player.data.stats.titanium_produced;
+            Leaderboard::record(LEADERBOARD_TITANIUM, &owner_id, score);
+        }
         event
     }
 }
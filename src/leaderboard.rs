@@ -0,0 +1,55 @@
+use crate::config::CONFIG;
+use crate::season::{update_ranking, Rank};
+use crate::MERKLE_MAP;
+use zkwasm_rest_abi::StorageData;
+
+const LEADERBOARD_KEY: u64 = 0xff06;
+
+pub const LEADERBOARD_TITANIUM: u64 = 0; // titanium produced by cards
+pub const LEADERBOARD_LEVEL: u64 = 1;
+pub const LEADERBOARD_OBJECTS: u64 = 2;
+
+/* Top players by one score, updated where the score of a player changes
+ *
+ * A player whose score drops out of the board is removed, the players below
+ * the board are not known so the board can be shorter than its size until
+ * they are stored again.
+ */
+pub struct Leaderboard;
+
+impl Leaderboard {
+    fn to_key(kind: u64) -> [u64; 4] {
+        [kind, 0, LEADERBOARD_KEY, 0]
+    }
+
+    pub fn get(kind: u64) -> Vec<Rank> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&Self::to_key(kind));
+        let mut u64data = data.iter_mut();
+        let size = u64data.next().map_or(0, |x| *x);
+        (0..size).map(|_| Rank::from_data(&mut u64data)).collect()
+    }
+
+    fn store(kind: u64, ranking: &[Rank]) {
+        let mut data = vec![ranking.len() as u64];
+        for r in ranking.iter() {
+            r.to_data(&mut data);
+        }
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&Self::to_key(kind), data.as_slice());
+    }
+
+    pub fn record(kind: u64, pid: &[u64; 2], score: u64) {
+        let mut ranking = Self::get(kind);
+        let current = ranking.iter().find(|r| r.pid == *pid).map(|r| r.score);
+        let lowest = ranking.last().map_or(0, |r| r.score);
+        let full = ranking.len() >= CONFIG.leaderboard_size();
+        // nothing to do if the score is unchanged or still too low to enter
+        let outside = current.is_none() && (score == 0 || (full && score <= lowest));
+        if current == Some(score) || outside {
+            return;
+        }
+        update_ranking(&mut ranking, pid, score, CONFIG.leaderboard_size());
+        Self::store(kind, &ranking);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::error::{ERROR_NOT_ENOUGH_BALANCE, ERROR_OVERFLOW};
+use crate::player::Pending;
+use serde::Serialize;
+
+/* Titanium accounting
+ *
+ * players is the titanium held by all players, deposited, minted and
+ * withdrawn count titanium entering and leaving the game and treasury
+ * collects every sink (action costs, fees, titanium consumed by cards).
+ * Together with the stakes and the pools held by State:
+ *
+ *   players + treasury + staked + stake_rewards + pooled + withdrawn
+ *     == deposited + minted
+ *
+ * Deployments that predate the ledger start with seeded unset, the admin
+ * adds the totals of the existing players once with SeedLedger.
+ */
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Ledger {
+    pub players: u64,
+    pub treasury: u64,
+    pub deposited: u64,
+    pub minted: u64,
+    pub withdrawn: u64,
+    pub seeded: bool,
+}
+
+impl Ledger {
+    /// Titanium moved by a command, see Pending. Nothing changes if a sum
+    /// overflows or the treasury can not pay what was granted.
+    pub fn apply(&mut self, pending: &Pending) -> Result<(), u32> {
+        // before the ledger is seeded players only counts what moved since
+        // the upgrade, which can be negative
+        let players = if self.seeded {
+            self.players
+                .checked_add_signed(pending.titanium)
+                .ok_or(ERROR_OVERFLOW)?
+        } else {
+            self.players.wrapping_add_signed(pending.titanium)
+        };
+        let treasury = self
+            .treasury
+            .checked_add(pending.sunk)
+            .ok_or(ERROR_OVERFLOW)?
+            .checked_sub(pending.granted)
+            .ok_or(ERROR_NOT_ENOUGH_BALANCE)?;
+        let deposited = self.deposited.checked_add(pending.deposited).ok_or(ERROR_OVERFLOW)?;
+        let minted = self.minted.checked_add(pending.minted).ok_or(ERROR_OVERFLOW)?;
+        let withdrawn = self.withdrawn.checked_add(pending.withdrawn).ok_or(ERROR_OVERFLOW)?;
+        self.players = players;
+        self.treasury = treasury;
+        self.deposited = deposited;
+        self.minted = minted;
+        self.withdrawn = withdrawn;
+        Ok(())
+    }
+
+    /// Take titanium out of the treasury to hand it out.
+    pub fn allocate(&mut self, amount: u64) -> Result<(), u32> {
+        if self.treasury < amount {
+            return Err(ERROR_NOT_ENOUGH_BALANCE);
+        }
+        self.treasury -= amount;
+        Ok(())
+    }
+}
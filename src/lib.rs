@@ -1,14 +1,44 @@
 #![feature(linked_list_cursors)]
 use wasm_bindgen::prelude::*;
 use zkwasm_rest_abi::*;
+pub mod amm;
+pub mod board;
 pub mod card;
 pub mod config;
+pub mod cost;
 pub mod error;
 pub mod events;
+pub mod leaderboard;
+pub mod ledger;
+pub mod market;
 pub mod object;
 pub mod player;
+pub mod season;
+pub mod stake;
 pub mod state;
+pub mod stats;
+pub mod trade;
 
 use crate::config::Config;
 use crate::state::{State, Transaction};
 zkwasm_rest_abi::create_zkwasm_apis!(Transaction, State, Config);
+
+#[wasm_bindgen]
+pub fn get_listings() -> String {
+    State::get_listings()
+}
+
+#[wasm_bindgen]
+pub fn get_bounty_board() -> String {
+    State::get_bounty_board()
+}
+
+#[wasm_bindgen]
+pub fn get_season(season: u32) -> String {
+    State::get_season(season)
+}
+
+#[wasm_bindgen]
+pub fn get_leaderboard(kind: u64) -> String {
+    State::get_leaderboard(kind)
+}
@@ -0,0 +1,105 @@
+use crate::card::Card;
+use crate::StorageData;
+use crate::MERKLE_MAP;
+use serde::Serialize;
+use std::slice::IterMut;
+
+const CARD_LISTING_KEY: u64 = 0xff02;
+const LISTING_INDEX_KEY: [u64; 4] = [0, 0, 0xff03, 0];
+
+/* A card listed on the global marketplace
+ *
+ * Like a card offer the card is held by the listing until it is bought,
+ * cancelled or expires, in the latter two cases it goes back to the seller.
+ * The ids of all open listings are kept in a single index leaf so that the
+ * market can be enumerated without scanning the whole id range.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct CardListing {
+    pub listing_id: u64,
+    pub seller: [u64; 2],
+    pub price: u64,
+    pub expire_at: u64,
+    pub card: Card,
+}
+
+impl CardListing {
+    fn to_key(listing_id: u64) -> [u64; 4] {
+        [listing_id, 0, CARD_LISTING_KEY, 0]
+    }
+
+    fn get_index() -> Vec<u64> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.get(&LISTING_INDEX_KEY)
+    }
+
+    fn set_index(index: &[u64]) {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&LISTING_INDEX_KEY, index);
+    }
+
+    pub fn get(listing_id: u64) -> Option<Self> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&Self::to_key(listing_id));
+        if data.is_empty() {
+            None
+        } else {
+            let mut u64data = data.iter_mut();
+            Some(Self::from_data(&mut u64data))
+        }
+    }
+
+    /// Store a new listing and register it in the listing index.
+    pub fn open(&self) {
+        let mut data = Vec::new();
+        self.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&Self::to_key(self.listing_id), data.as_slice());
+        let mut index = Self::get_index();
+        index.push(self.listing_id);
+        Self::set_index(&index);
+    }
+
+    /// Remove a listing and drop it from the listing index.
+    pub fn close(listing_id: u64) {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&Self::to_key(listing_id), &[]);
+        let index = Self::get_index()
+            .into_iter()
+            .filter(|x| *x != listing_id)
+            .collect::<Vec<_>>();
+        Self::set_index(&index);
+    }
+
+    pub fn all() -> Vec<Self> {
+        Self::get_index()
+            .into_iter()
+            .filter_map(Self::get)
+            .collect()
+    }
+}
+
+impl StorageData for CardListing {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let listing_id = *u64data.next().unwrap();
+        let seller = [*u64data.next().unwrap(), *u64data.next().unwrap()];
+        let price = *u64data.next().unwrap();
+        let expire_at = *u64data.next().unwrap();
+        let card = Card::from_data(u64data);
+        CardListing {
+            listing_id,
+            seller,
+            price,
+            expire_at,
+            card,
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.listing_id);
+        data.push(self.seller[0]);
+        data.push(self.seller[1]);
+        data.push(self.price);
+        data.push(self.expire_at);
+        self.card.to_data(data);
+    }
+}
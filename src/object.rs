@@ -26,6 +26,21 @@ impl Object {
             attributes: [0, 0, 0, 0],
         }
     }
+    /// The class of an object is its most upgraded feature (1: speed,
+    /// 2: efficiency, 3: productivity) or 0 if it has not been upgraded.
+    /// attributes[0] is the level and not a feature.
+    pub fn class(&self) -> usize {
+        let mut class = 0;
+        let mut best = 0;
+        for (i, v) in self.attributes.iter().enumerate().skip(1) {
+            if *v > best {
+                best = *v;
+                class = i;
+            }
+        }
+        class
+    }
+
     pub fn halt(&mut self) {
         self.modifier_info = (self.modifier_info & 0xFFFFFFFFFFFFFF) | 1 << 56;
     }
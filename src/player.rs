@@ -1,8 +1,20 @@
+use crate::amm::Position;
+use crate::board::BoardBounty;
 use crate::card::{Card, DEFAULT_CARDS};
-use crate::config::COST_INCREASE_ROUND;
-use crate::config::{default_local, random_modifier, INITIAL_ENERGY};
-use crate::error::ERROR_NOT_ENOUGH_BALANCE;
+use crate::cost::{CostCounter, COST_ACTIONS};
+use crate::config::{
+    card_weight, crafted_modifier, default_local, fuse_modifier, random_modifier, resource_weight, CONFIG,
+    INITIAL_ENERGY,
+    LOCAL_ATTRIBUTES_SIZE, RECYCLE_REFUND_DIVISOR, STAKE_LOCKS,
+};
+use crate::error::{
+    ERROR_BOUNTY_CLAIMED, ERROR_BOUNTY_LIMIT, ERROR_BOUNTY_NOT_ELIGIBLE, ERROR_CARD_IN_USE, ERROR_INDEX_OUT_OF_BOUND, ERROR_INVALID_CRAFT, ERROR_INVALID_FUSION, ERROR_NOT_ENOUGH_BALANCE,
+    ERROR_NOT_ENOUGH_LIQUIDITY, ERROR_NOT_ENOUGH_RESOURCE, ERROR_OVERFLOW, ERROR_STAKE_LOCKED,
+    ERROR_TOO_MANY_STAKES, ERROR_TRANSFER_LIMIT,
+};
 use crate::object::Object;
+use crate::stake::Stake;
+use crate::stats::PlayerStats;
 use crate::Player;
 use crate::StorageData;
 use crate::MERKLE_MAP;
@@ -12,17 +24,100 @@ use std::slice::IterMut;
 #[derive(Clone, Debug, Serialize)]
 pub struct Attributes(pub Vec<i64>);
 
+/// Resource amounts are u64 in commands but balances are i64.
+pub fn signed_amount(amount: u64) -> Result<i64, u32> {
+    i64::try_from(amount).map_err(|_| ERROR_OVERFLOW)
+}
+
 impl Attributes {
-    pub fn apply_modifier(&mut self, m: &Attributes) -> bool {
+    pub fn apply_modifier(&mut self, m: &Attributes) -> Result<bool, u32> {
+        let mut updated = Vec::with_capacity(self.0.len());
         for (a, b) in self.0.iter().zip(m.0.iter()) {
-            if *a + *b < 0 {
-                return false;
+            let v = a.checked_add(*b).ok_or(ERROR_OVERFLOW)?;
+            if v < 0 {
+                return Ok(false);
             }
+            updated.push(v);
         }
-        for (a, b) in self.0.iter_mut().zip(m.0.iter()) {
-            *a += *b;
+        for (a, v) in self.0.iter_mut().zip(updated) {
+            *a = v;
         }
-        return true;
+        Ok(true)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BountyQuote {
+    pub cost: u64, // resource taken by the next redeem
+    pub reward: u64, // titanium given by the next redeem
+    pub remaining: u8, // redeems left in the current period
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnergyEstimate {
+    pub collectable: u64, // energy CollectEnergy would add now
+    pub max_energy: u64,
+    pub full_in: u64, // ticks until collecting would reach max_energy
+}
+
+/* Changes a player command makes to the global State
+ *
+ * Player methods only record them here and State::settle applies them once
+ * the command succeeded, so a command failing halfway leaves State as it was.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Pending {
+    pub energy: u64, // drawn from the global supply
+    pub stake_fee: u64, // titanium paid into the stake rewards
+    pub titanium: i64, // gained (or lost if negative) by the player
+    pub minted: u64,
+    pub sunk: u64, // titanium paid to the treasury
+    pub granted: u64, // titanium handed out of the treasury
+    pub deposited: u64,
+    pub withdrawn: u64,
+}
+
+impl Pending {
+    pub fn record(&mut self, delta: i64) {
+        self.titanium = self.titanium.wrapping_add(delta);
+    }
+
+    pub fn mint(&mut self, amount: u64) {
+        self.minted = self.minted.saturating_add(amount);
+    }
+
+    pub fn sink(&mut self, amount: u64) {
+        self.sunk = self.sunk.saturating_add(amount);
+    }
+
+    pub fn grant(&mut self, amount: u64) {
+        self.granted = self.granted.saturating_add(amount);
+    }
+
+    pub fn deposit(&mut self, amount: u64) {
+        self.deposited = self.deposited.saturating_add(amount);
+    }
+
+    pub fn withdraw(&mut self, amount: u64) {
+        self.withdrawn = self.withdrawn.saturating_add(amount);
+    }
+
+    /// Titanium a new player starts with.
+    pub fn new_player(&mut self) {
+        let initial = default_local()[CONFIG.titanium()];
+        self.record(initial);
+        self.mint(initial as u64);
+    }
+
+    pub fn merge(&mut self, other: Pending) {
+        self.energy = self.energy.saturating_add(other.energy);
+        self.stake_fee = self.stake_fee.saturating_add(other.stake_fee);
+        self.record(other.titanium);
+        self.mint(other.minted);
+        self.sink(other.sunk);
+        self.grant(other.granted);
+        self.deposit(other.deposited);
+        self.withdraw(other.withdrawn);
     }
 }
 
@@ -32,12 +127,25 @@ pub struct PlayerData {
     pub exp: u16,
     pub last_check_point: u32,
     pub energy: u16, // this is collected from the supplier
-    pub cost_info: u16,
-    pub current_cost: u32,
-    pub redeem_info: [u8; 8],
+    pub costs: [CostCounter; COST_ACTIONS], // indexed by the COST_* actions
+    pub redeem_info: [u8; LOCAL_ATTRIBUTES_SIZE], // redeems in the current bounty period
+    pub bounty_epoch: u32,
+    pub board_epoch: u32,
+    pub board_claims: u32, // bit i is set once request i of the board is fulfilled
+    pub season: u32,
+    pub season_score: u64, // titanium earned from bounties in the season
+    pub stakes: Vec<Stake>,
     pub objects: Vec<Object>,
     pub local: Attributes,
     pub cards: Vec<Card>,
+    pub stats: PlayerStats,
+    pub reroll_count: u16,
+    pub reroll_epoch: u32,
+    pub liquidity: Vec<Position>,
+    pub transfer_day: u32,
+    pub transferred: u32, // weighted value sent during transfer_day
+    #[serde(skip)]
+    pub pending: Pending,
 }
 
 impl Default for PlayerData {
@@ -47,12 +155,24 @@ impl Default for PlayerData {
             exp: 0,
             last_check_point: 0,
             energy: INITIAL_ENERGY,
-            cost_info: COST_INCREASE_ROUND,
-            current_cost: 0,
-            redeem_info: [0; 8],
+            costs: [CostCounter::default(); COST_ACTIONS],
+            redeem_info: [0; LOCAL_ATTRIBUTES_SIZE],
+            bounty_epoch: 0,
+            board_epoch: 0,
+            board_claims: 0,
+            season: 0,
+            season_score: 0,
+            stakes: vec![],
             objects: vec![],
             local: Attributes::default_local(),
             cards: DEFAULT_CARDS.clone(),
+            stats: PlayerStats::default(),
+            reroll_count: 0,
+            reroll_epoch: 0,
+            liquidity: vec![],
+            transfer_day: 0,
+            transferred: 0,
+            pending: Pending::default(),
         }
     }
 }
@@ -64,35 +184,372 @@ impl Attributes {
 }
 
 impl PlayerData {
-    pub fn generate_card(&mut self, rand: &[u64; 4]) {
-        let new_card = random_modifier(self.local.0.clone().try_into().unwrap(), rand[1]);
+    /// paid is the titanium the card cost, see pay_cost.
+    pub fn generate_card(&mut self, rand: &[u64; 4], paid: u64) {
+        let mut new_card = random_modifier(self.local.0.clone().try_into().unwrap(), rand[1]);
+        new_card.paid = paid;
         self.cards.push(new_card)
     }
 
-    pub fn pay_cost(&mut self) -> Result<(), u32> {
-        self.cost_balance(self.current_cost as i64)?;
-        self.cost_info -= 1;
-        if self.cost_info == 0 {
-            self.cost_info = COST_INCREASE_ROUND;
-            if self.current_cost != 0 {
-                self.current_cost = self.current_cost * 2
-            } else {
-                self.current_cost = 1;
+    /// Check the outputs chosen for a crafted card, run before the craft is
+    /// paid for.
+    pub fn validate_craft(&self, outputs: [usize; 2]) -> Result<(), u32> {
+        if outputs.iter().any(|x| *x >= LOCAL_ATTRIBUTES_SIZE) {
+            return Err(ERROR_INDEX_OUT_OF_BOUND);
+        }
+        Ok(())
+    }
+
+    pub fn craft_card(&mut self, outputs: [usize; 2], rand: &[u64; 4], paid: u64) -> Result<(), u32> {
+        self.validate_craft(outputs)?;
+        let premium = CONFIG.craft_premium();
+        self.cost_balance(premium as i64)?;
+        self.stats.titanium_spent = self.stats.titanium_spent.saturating_add(premium);
+        self.pending.sink(premium);
+        let mut new_card = crafted_modifier(self.local.0.clone().try_into().unwrap(), rand[1], outputs);
+        // a crafted card has to consume something for what it produces
+        if new_card.attributes.iter().all(|a| *a >= 0) {
+            return Err(ERROR_INVALID_CRAFT);
+        }
+        new_card.paid = paid.saturating_add(premium);
+        self.cards.push(new_card);
+        Ok(())
+    }
+
+    /// Replace a card that has never been run nor placed in an object with a
+    /// new random draw. Rerolls get twice as expensive each time until the
+    /// reroll period rolls over.
+    pub fn reroll_card(&mut self, card_index: usize, rand: &[u64; 4], counter: u64) -> Result<(), u32> {
+        let card = self.cards.get(card_index).ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
+        if card.usage != 0 || card.level != 0 || self.card_in_use(card_index) {
+            return Err(ERROR_CARD_IN_USE);
+        }
+        let epoch = CONFIG.reroll_epoch(counter);
+        if epoch != self.reroll_epoch {
+            self.reroll_epoch = epoch;
+            self.reroll_count = 0;
+        }
+        let cost = CONFIG.reroll_cost(self.reroll_count);
+        self.cost_balance(cost as i64)?;
+        self.stats.titanium_spent = self.stats.titanium_spent.saturating_add(cost);
+        self.pending.sink(cost);
+        self.reroll_count = self.reroll_count.saturating_add(1);
+        let paid = self.cards[card_index].paid.saturating_add(cost);
+        self.cards[card_index] = random_modifier(self.local.0.clone().try_into().unwrap(), rand[1]);
+        self.cards[card_index].paid = paid;
+        Ok(())
+    }
+
+    pub fn card_in_use(&self, card_index: usize) -> bool {
+        self.objects
+            .iter()
+            .any(|o| o.cards.iter().any(|c| *c as usize == card_index))
+    }
+
+    /// Remove a card that no object program refers to and shift the card
+    /// indexes of all objects so that they keep pointing at the same cards.
+    pub fn remove_card(&mut self, card_index: usize) -> Result<Card, u32> {
+        if card_index >= self.cards.len() {
+            return Err(ERROR_INDEX_OUT_OF_BOUND);
+        }
+        if self.card_in_use(card_index) {
+            return Err(ERROR_CARD_IN_USE);
+        }
+        for object in self.objects.iter_mut() {
+            for c in object.cards.iter_mut() {
+                if *c as usize > card_index {
+                    *c -= 1;
+                }
             }
         }
-        let energy = (self.energy as u32) + 20;
-        if energy > 0xffff {
-            self.energy = 0xffff
+        Ok(self.cards.remove(card_index))
+    }
+
+    /// Remove a card and refund part of its weight in titanium, never more
+    /// than was paid for the card so that free cards return nothing.
+    pub fn recycle_card(&mut self, card_index: usize) -> Result<(), u32> {
+        let card = self.remove_card(card_index)?;
+        let titanium = CONFIG.titanium();
+        let value = card_weight(&card.attributes).max(0) as u64 / RECYCLE_REFUND_DIVISOR;
+        let refund = (value / resource_weight(titanium)).min(card.paid);
+        self.add_resource(titanium, refund)?;
+        self.pending.mint(refund);
+        Ok(())
+    }
+
+    /// Check that two cards can be fused, run before the fusion is paid for.
+    pub fn validate_fusion(&self, index_a: usize, index_b: usize) -> Result<(), u32> {
+        if index_a == index_b {
+            return Err(ERROR_INVALID_FUSION);
+        }
+        if index_a >= self.cards.len() || index_b >= self.cards.len() {
+            return Err(ERROR_INDEX_OUT_OF_BOUND);
+        }
+        if self.card_in_use(index_a) || self.card_in_use(index_b) {
+            return Err(ERROR_CARD_IN_USE);
+        }
+        Ok(())
+    }
+
+    /// The fused card was paid for by both cards and the fusion cost paid.
+    pub fn fuse_cards(&mut self, index_a: usize, index_b: usize, paid: u64) -> Result<(), u32> {
+        self.validate_fusion(index_a, index_b)?;
+        // remove the higher index first so the lower one stays valid
+        let (low, high) = if index_a < index_b { (index_a, index_b) } else { (index_b, index_a) };
+        let card_high = self.remove_card(high)?;
+        let card_low = self.remove_card(low)?;
+        let mut new_card = fuse_modifier(&card_low, &card_high);
+        new_card.paid = new_card.paid.saturating_add(paid);
+        self.cards.push(new_card);
+        Ok(())
+    }
+
+    pub fn next_cost(&self, action: usize, counter: u64) -> Result<u64, u32> {
+        let curve = CONFIG.cost_curve(action);
+        let c = self.costs[action];
+        curve.cost(curve.decay(c.paid, counter.saturating_sub(c.last_paid as u64)) as u64)
+    }
+
+    /// Charge the next cost of an action, the energy bonus that comes with it
+    /// is taken from supply, the energy left in the global supply. Returns
+    /// the titanium paid.
+    pub fn pay_cost(&mut self, action: usize, counter: u64, supply: u64) -> Result<u64, u32> {
+        let cost = self.next_cost(action, counter)?;
+        if cost > i64::MAX as u64 {
+            return Err(ERROR_OVERFLOW);
+        }
+        self.cost_balance(cost as i64)?;
+        self.stats.titanium_spent = self.stats.titanium_spent.saturating_add(cost);
+        let stake_fee = CONFIG.stake_fee(cost);
+        self.pending.stake_fee = self.pending.stake_fee.saturating_add(stake_fee);
+        self.pending.sink(cost - stake_fee);
+        let curve = CONFIG.cost_curve(action);
+        let c = &mut self.costs[action];
+        c.paid = curve
+            .decay(c.paid, counter.saturating_sub(c.last_paid as u64))
+            .checked_add(1)
+            .ok_or(ERROR_OVERFLOW)?;
+        c.last_paid = counter as u32;
+        let wanted = CONFIG.cost_energy_bonus().min(0xffff - self.energy as u64);
+        self.energy += self.draw_energy(wanted, supply) as u16;
+        Ok(cost)
+    }
+
+    /// Take up to amount energy from the global supply, returns what was taken.
+    fn draw_energy(&mut self, amount: u64, supply: u64) -> u64 {
+        let drawn = amount.min(supply.saturating_sub(self.pending.energy));
+        self.pending.energy += drawn;
+        drawn
+    }
+
+    pub fn take_resource(&mut self, index: usize, amount: u64) -> Result<(), u32> {
+        let v = self.local.0.get_mut(index).ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
+        if amount > i64::MAX as u64 || *v < amount as i64 {
+            Err(ERROR_NOT_ENOUGH_RESOURCE)
+        } else {
+            *v -= amount as i64;
+            if index == CONFIG.titanium() {
+                self.pending.record(-(amount as i64));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn add_shares(&mut self, pool: u64, shares: u64) -> Result<(), u32> {
+        match self.liquidity.iter_mut().find(|p| p.pool == pool) {
+            Some(position) => {
+                position.shares = position.shares.checked_add(shares).ok_or(ERROR_OVERFLOW)?
+            }
+            None => self.liquidity.push(Position { pool, shares }),
+        }
+        Ok(())
+    }
+
+    pub fn remove_shares(&mut self, pool: u64, shares: u64) -> Result<(), u32> {
+        let index = self
+            .liquidity
+            .iter()
+            .position(|p| p.pool == pool && p.shares >= shares)
+            .ok_or(ERROR_NOT_ENOUGH_LIQUIDITY)?;
+        self.liquidity[index].shares -= shares;
+        if self.liquidity[index].shares == 0 {
+            self.liquidity.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Take amount of a resource to send it to another player, the daily cap
+    /// is counted in weighted value. Returns the amount that is delivered.
+    pub fn send_resource(&mut self, index: usize, amount: u64, counter: u64) -> Result<u64, u32> {
+        self.take_resource(index, amount)?;
+        let day = CONFIG.day(counter);
+        if day != self.transfer_day {
+            self.transfer_day = day;
+            self.transferred = 0;
+        }
+        let value = amount
+            .checked_mul(resource_weight(index))
+            .and_then(|x| x.checked_add(self.transferred as u64))
+            .ok_or(ERROR_TRANSFER_LIMIT)?;
+        let cap = CONFIG.transfer_daily_cap();
+        if (cap != 0 && value > cap) || value > u32::MAX as u64 {
+            return Err(ERROR_TRANSFER_LIMIT);
+        }
+        self.transferred = value as u32;
+        self.stats.sent = self.stats.sent.saturating_add(amount);
+        Ok(amount - CONFIG.transfer_fee(amount))
+    }
+
+    pub fn add_resource(&mut self, index: usize, amount: u64) -> Result<(), u32> {
+        let v = self.local.0.get_mut(index).ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
+        *v = v.checked_add(signed_amount(amount)?).ok_or(ERROR_OVERFLOW)?;
+        if index == CONFIG.titanium() {
+            self.pending.record(amount as i64);
+        }
+        Ok(())
+    }
+
+    /// Credit a resource sent by another player.
+    pub fn receive_resource(&mut self, index: usize, amount: u64) -> Result<(), u32> {
+        self.add_resource(index, amount)?;
+        self.stats.received = self.stats.received.saturating_add(amount);
+        Ok(())
+    }
+
+    /// Take titanium out of the game, the settlement is left to the caller.
+    pub fn withdraw(&mut self, amount: u64) -> Result<(), u32> {
+        self.cost_balance(signed_amount(amount)?)?;
+        self.stats.withdrawn = self.stats.withdrawn.saturating_add(amount);
+        self.pending.withdraw(amount);
+        Ok(())
+    }
+
+    fn redeemed(&self, index: usize, counter: u64) -> u8 {
+        if CONFIG.bounty_epoch(counter) == self.bounty_epoch {
+            self.redeem_info[index]
         } else {
-            self.energy += 20;
+            0
+        }
+    }
+
+    /// Cost and reward of the next redeem of a resource, None if it can not
+    /// be redeemed anymore in the current period.
+    pub fn next_bounty(&self, index: usize, counter: u64) -> Option<BountyQuote> {
+        let bounty = CONFIG.resource(index)?.bounty?;
+        let redeemed = self.redeemed(index, counter);
+        if redeemed >= bounty.cap {
+            return None;
         }
+        Some(BountyQuote {
+            cost: bounty.cost(redeemed).ok()?,
+            reward: bounty.reward(redeemed).ok()?,
+            remaining: bounty.cap - redeemed,
+        })
+    }
+
+    /// Redeem a resource for titanium, returns the reward.
+    pub fn redeem_bounty(&mut self, index: usize, counter: u64) -> Result<u64, u32> {
+        let bounty = CONFIG
+            .resource(index)
+            .ok_or(ERROR_INDEX_OUT_OF_BOUND)?
+            .bounty
+            .ok_or(ERROR_BOUNTY_NOT_ELIGIBLE)?;
+        let redeemed = self.redeemed(index, counter);
+        if redeemed >= bounty.cap {
+            return Err(ERROR_BOUNTY_LIMIT);
+        }
+        let epoch = CONFIG.bounty_epoch(counter);
+        if epoch != self.bounty_epoch {
+            self.bounty_epoch = epoch;
+            self.redeem_info = [0; LOCAL_ATTRIBUTES_SIZE];
+        }
+        self.take_resource(index, bounty.cost(redeemed)?)?;
+        let reward = bounty.reward(redeemed)?;
+        self.cost_balance(-signed_amount(reward)?)?;
+        self.redeem_info[index] = redeemed + 1;
+        self.stats.bounty_reward = self.stats.bounty_reward.saturating_add(reward);
+        self.season_score = self.season_score.saturating_add(reward);
+        self.pending.mint(reward);
+        Ok(reward)
+    }
+
+    /// Deliver the resource asked by request index of the board epoch,
+    /// returns the reward.
+    pub fn fulfill_bounty(
+        &mut self,
+        epoch: u32,
+        index: usize,
+        bounty: &BoardBounty,
+    ) -> Result<u64, u32> {
+        if bounty.remaining == 0 {
+            return Err(ERROR_BOUNTY_LIMIT);
+        }
+        if epoch != self.board_epoch {
+            self.board_epoch = epoch;
+            self.board_claims = 0;
+        }
+        if self.board_claims & (1 << index) != 0 {
+            return Err(ERROR_BOUNTY_CLAIMED);
+        }
+        self.take_resource(bounty.resource, bounty.amount)?;
+        self.cost_balance(-signed_amount(bounty.reward)?)?;
+        self.board_claims |= 1 << index;
+        self.stats.bounty_reward = self.stats.bounty_reward.saturating_add(bounty.reward);
+        self.season_score = self.season_score.saturating_add(bounty.reward);
+        self.pending.mint(bounty.reward);
+        Ok(bounty.reward)
+    }
+
+    pub fn stake(&mut self, lock: usize, amount: u64, counter: u64) -> Result<(), u32> {
+        if lock >= STAKE_LOCKS {
+            return Err(ERROR_INDEX_OUT_OF_BOUND);
+        }
+        if self.stakes.len() >= CONFIG.stake_max_positions() {
+            return Err(ERROR_TOO_MANY_STAKES);
+        }
+        self.cost_balance(signed_amount(amount)?)?;
+        self.stakes.push(Stake {
+            amount,
+            lock: lock as u32,
+            start: counter as u32,
+        });
         Ok(())
     }
 
+    /// Remove a stake whose lock period is over, the caller pays it back.
+    pub fn unstake(&mut self, index: usize, counter: u64) -> Result<Stake, u32> {
+        let stake = self.stakes.get(index).ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
+        if counter < stake.unlock_at() {
+            return Err(ERROR_STAKE_LOCKED);
+        }
+        Ok(self.stakes.remove(index))
+    }
+
+    /// Move the player into the season, the score starts over and resources
+    /// (but titanium) are cut down to season_keep_percent. Returns false if
+    /// the player is already in the season.
+    pub fn enter_season(&mut self, season: u32) -> bool {
+        if season == self.season {
+            return false;
+        }
+        self.season = season;
+        self.season_score = 0;
+        let keep = CONFIG.season_keep_percent();
+        if keep < 100 {
+            for (i, v) in self.local.0.iter_mut().enumerate() {
+                if i != CONFIG.titanium() && *v > 0 {
+                    *v = *v / 100 * keep + *v % 100 * keep / 100;
+                }
+            }
+        }
+        true
+    }
+
     pub fn cost_balance(&mut self, b: i64) -> Result<(), u32> {
-        if let Some(treasure) = self.local.0.last_mut() {
+        if let Some(treasure) = self.local.0.get_mut(CONFIG.titanium()) {
             if *treasure >= b {
-                *treasure -= b;
+                *treasure = treasure.checked_sub(b).ok_or(ERROR_OVERFLOW)?;
+                self.pending.record(-b);
                 Ok(())
             } else {
                 Err(ERROR_NOT_ENOUGH_BALANCE)
@@ -110,6 +567,10 @@ impl PlayerData {
     }
 
     pub fn inc_exp(&mut self) {
+        // the level stops at u16::MAX and keeps the experience full
+        if self.level == u16::MAX {
+            return;
+        }
         self.exp += 1;
         if self.exp >= 100 {
             self.level += 1;
@@ -117,14 +578,51 @@ impl PlayerData {
         }
     }
 
-    pub fn collect_energy(&mut self, counter: u64) -> Result <(), u32> {
+    /// Energy regenerated since last_check_point (one per energy_regen_ticks
+    /// per level) and the ticks it took, the remaining ticks carry over.
+    pub fn pending_energy(&self, counter: u64) -> (u64, u64) {
         let delta = counter - (self.last_check_point as u64);
-        let energe = delta * (self.level as u64);
-        if energe > 1000 {
-            self.energy += 10;
+        let level = self.level.max(1) as u64;
+        let regen_ticks = CONFIG.energy_regen_ticks();
+        let energy = delta * level / regen_ticks;
+        (energy, energy * regen_ticks / level)
+    }
+
+    pub fn energy_estimate(&self, counter: u64) -> EnergyEstimate {
+        let (pending, _) = self.pending_energy(counter);
+        let max_energy = CONFIG.max_energy(self.level);
+        let missing = max_energy.saturating_sub(self.energy as u64);
+        let collectable = pending.min(missing);
+        let level = self.level.max(1) as u64;
+        let full_in = (missing - collectable) * CONFIG.energy_regen_ticks() / level;
+        EnergyEstimate {
+            collectable,
+            max_energy,
+            full_in,
+        }
+    }
+
+    pub fn collect_energy(&mut self, counter: u64, supply: u64) -> Result <(), u32> {
+        let cost = CONFIG.energy_collect_cost();
+        self.cost_balance(cost as i64)?;
+        self.stats.titanium_spent = self.stats.titanium_spent.saturating_add(cost);
+        self.pending.sink(cost);
+        let (pending, _) = self.pending_energy(counter);
+        let missing = CONFIG.max_energy(self.level).saturating_sub(self.energy as u64);
+        let gained = self.draw_energy(pending.min(missing), supply);
+        self.energy = u16::try_from(gained)
+            .ok()
+            .and_then(|x| self.energy.checked_add(x))
+            .ok_or(ERROR_OVERFLOW)?;
+        if pending >= missing && gained == missing {
+            // nothing accumulates while the energy is full
+            self.last_check_point = counter as u32;
+        } else {
+            // ticks the global supply could not pay for stay pending
+            let level = self.level.max(1) as u64;
+            self.last_check_point += (gained * CONFIG.energy_regen_ticks() / level) as u32;
         }
-        self.last_check_point = counter as u32;
-        self.cost_balance(1)
+        Ok(())
     }
 
     pub fn apply_object_card(&mut self, object_index: usize, counter: u64) -> Option<usize> {
@@ -141,19 +639,32 @@ impl PlayerData {
             Some(duration as usize)
         } else {
             let card = self.cards[object.cards[current_index] as usize].clone();
-            let applied = self.apply_modifier(&card, &object);
+            let energy_cost = card.energy_cost() + CONFIG.object_class_energy(object.class());
+            if (self.energy as u64) < energy_cost {
+                self.objects[object_index].halt();
+                self.stats.halts = self.stats.halts.saturating_add(1);
+                return None;
+            }
+            // a modifier that would overflow a resource halts the object
+            let applied = self.apply_modifier(&card, &object).unwrap_or(false);
             zkwasm_rust_sdk::dbg!("applied modifier!\n");
             let object = self.objects.get_mut(object_index).unwrap();
             if applied {
+                self.energy -= energy_cost as u16;
                 //zkwasm_rust_sdk::dbg!("object after: {:?}\n", object);
                 //zkwasm_rust_sdk::dbg!("player after: {:?}\n", {&self.local});
+                self.cards[object.cards[current_index] as usize].record_use();
                 let next_index = (current_index + 1) % object.cards.len();
+                if next_index == 0 {
+                    self.stats.cycles = self.stats.cycles.saturating_add(1);
+                }
                 let duration = self.cards[object.cards[next_index] as usize].duration;
                 let duration = if duration > speed { duration - speed} else { 1 };
                 object.start_new_modifier(next_index, counter);
                 Some(duration as usize)
             } else {
                 object.halt();
+                self.stats.halts = self.stats.halts.saturating_add(1);
                 None
             }
         }
@@ -181,19 +692,34 @@ impl PlayerData {
             None
         }
     }
-    pub fn apply_modifier(&mut self, m: &Card, o: &Object) -> bool {
+    pub fn apply_modifier(&mut self, m: &Card, o: &Object) -> Result<bool, u32> {
         let reduce = o.attributes[2] as i64;
         let productivity = o.attributes[3] as i64;
         let m = m.attributes.iter().map(|x| *x as i64).collect::<Vec<_>>();
+        let mut updated = Vec::with_capacity(m.len());
         for (a, b) in self.local.0.iter().zip(m.iter()) {
-            if *a + *b + reduce < 0 {
-                return false;
+            let check = a.checked_add(*b).and_then(|x| x.checked_add(reduce));
+            if check.ok_or(ERROR_OVERFLOW)? < 0 {
+                return Ok(false);
             }
+            let delta = b.checked_add(productivity).ok_or(ERROR_OVERFLOW)?;
+            let v = a.checked_add(delta).ok_or(ERROR_OVERFLOW)?;
+            updated.push((v, delta));
         }
-        for (a, b) in self.local.0.iter_mut().zip(m.iter()) {
-            *a += *b + productivity;
+        for (i, (a, (v, delta))) in self.local.0.iter_mut().zip(updated).enumerate() {
+            *a = v;
+            self.stats.record_delta(delta);
+            if i == CONFIG.titanium() {
+                self.pending.record(delta);
+                if delta > 0 {
+                    self.stats.titanium_produced = self.stats.titanium_produced.saturating_add(delta as u64);
+                    self.pending.mint(delta as u64);
+                } else {
+                    self.pending.sink(delta.unsigned_abs());
+                }
+            }
         }
-        return true;
+        Ok(true)
     }
 }
 
@@ -213,23 +739,83 @@ impl StorageData for PlayerData {
         for _ in 0..local_size {
             local.push(*u64data.next().unwrap() as i64);
         }
+        // players stored before a resource was added start with none of it
+        local.resize(LOCAL_ATTRIBUTES_SIZE, 0);
 
         let card_size = *u64data.next().unwrap();
         let mut cards = Vec::with_capacity(card_size as usize);
         for _ in 0..card_size {
             cards.push(Card::from_data(u64data));
         }
+        let stats = PlayerStats::from_data(u64data);
+        let reroll_info = u64data.next().map_or(0, |x| *x);
+
+        // the first eight redeem counters live in redeem_info, the rest follow at the end
+        let mut redeem = [0u8; LOCAL_ATTRIBUTES_SIZE];
+        redeem[..8].copy_from_slice(&redeem_info.to_le_bytes());
+        let redeem_words = u64data.next().map_or(0, |x| *x) as usize;
+        for w in 0..redeem_words {
+            let v = *u64data.next().unwrap();
+            for (i, b) in v.to_le_bytes().iter().enumerate() {
+                let index = 8 + w * 8 + i;
+                if index < LOCAL_ATTRIBUTES_SIZE {
+                    redeem[index] = *b;
+                }
+            }
+        }
+        let liquidity_size = u64data.next().map_or(0, |x| *x);
+        let mut liquidity = Vec::with_capacity(liquidity_size as usize);
+        for _ in 0..liquidity_size {
+            liquidity.push(Position::from_data(u64data));
+        }
+        let transfer_info = u64data.next().map_or(0, |x| *x);
+        let costs_size = u64data.next().map_or(0, |x| *x);
+        let costs = if costs_size == 0 {
+            // stored before the cost curves, every action starts from the old counter
+            let legacy = CostCounter::from_legacy(
+                ((cost_info >> 32) & 0xffff) as u16,
+                (cost_info & 0xffffffff) as u32,
+            );
+            [legacy; COST_ACTIONS]
+        } else {
+            let mut costs = [CostCounter::default(); COST_ACTIONS];
+            for i in 0..costs_size as usize {
+                let c = CostCounter::from_data(u64data);
+                if i < COST_ACTIONS {
+                    costs[i] = c;
+                }
+            }
+            costs
+        };
+        let bounty_epoch = u64data.next().map_or(0, |x| *x) as u32;
+        let board_info = u64data.next().map_or(0, |x| *x);
+        let season = u64data.next().map_or(0, |x| *x) as u32;
+        let season_score = u64data.next().map_or(0, |x| *x);
+        let stakes_size = u64data.next().map_or(0, |x| *x);
+        let stakes = (0..stakes_size).map(|_| Stake::from_data(u64data)).collect();
         PlayerData {
             level: ((player_info >> 48) & 0xffff) as u16,
             exp: ((player_info >> 32) & 0xffff) as u16,
             last_check_point : (player_info & 0xffffffff) as u32,
             energy: ((cost_info >> 48) & 0xffff) as u16,
-            cost_info: ((cost_info >> 32) & 0xffff) as u16,
-            redeem_info: redeem_info.to_le_bytes(),
-            current_cost: (cost_info & 0xffffffff) as u32,
+            redeem_info: redeem,
+            bounty_epoch,
+            board_epoch: (board_info >> 32) as u32,
+            board_claims: (board_info & 0xffffffff) as u32,
+            season,
+            season_score,
+            stakes,
+            costs,
             objects,
             local: Attributes(local),
             cards,
+            stats,
+            reroll_count: (reroll_info & 0xffff) as u16,
+            reroll_epoch: (reroll_info >> 16) as u32,
+            liquidity,
+            transfer_day: (transfer_info >> 32) as u32,
+            transferred: (transfer_info & 0xffffffff) as u32,
+            pending: Pending::default(),
         }
     }
     fn to_data(&self, data: &mut Vec<u64>) {
@@ -238,12 +824,9 @@ impl StorageData for PlayerData {
                 + ((self.exp as u64) << 32)
                 + (self.last_check_point as u64),
         );
-        data.push(
-            ((self.energy as u64) << 48)
-            + ((self.cost_info as u64) << 32)
-            + (self.current_cost as u64),
-        );
-        data.push(u64::from_le_bytes(self.redeem_info));
+        // the lower 48 bits used to hold the legacy cost counter
+        data.push((self.energy as u64) << 48);
+        data.push(u64::from_le_bytes(self.redeem_info[..8].try_into().unwrap()));
         data.push(self.objects.len() as u64);
         for c in self.objects.iter() {
             c.to_data(data);
@@ -256,6 +839,32 @@ impl StorageData for PlayerData {
         for c in self.cards.iter() {
             c.to_data(data);
         }
+        self.stats.to_data(data);
+        data.push(((self.reroll_epoch as u64) << 16) + (self.reroll_count as u64));
+        let redeem_extra = &self.redeem_info[8..];
+        data.push(((redeem_extra.len() + 7) / 8) as u64);
+        for chunk in redeem_extra.chunks(8) {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            data.push(u64::from_le_bytes(bytes));
+        }
+        data.push(self.liquidity.len() as u64);
+        for p in self.liquidity.iter() {
+            p.to_data(data);
+        }
+        data.push(((self.transfer_day as u64) << 32) + (self.transferred as u64));
+        data.push(COST_ACTIONS as u64);
+        for c in self.costs.iter() {
+            c.to_data(data);
+        }
+        data.push(self.bounty_epoch as u64);
+        data.push(((self.board_epoch as u64) << 32) + (self.board_claims as u64));
+        data.push(self.season as u64);
+        data.push(self.season_score);
+        data.push(self.stakes.len() as u64);
+        for s in self.stakes.iter() {
+            s.to_data(data);
+        }
     }
 }
 
@@ -284,3 +893,181 @@ impl Owner for AutomataPlayer {
         Self::get_from_pid(&Self::pkey_to_pid(pkey))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_titanium(player: &mut PlayerData, amount: i64) {
+        player.local.0[CONFIG.titanium()] = amount;
+    }
+
+    #[test]
+    fn signed_amount_bounds() {
+        assert_eq!(signed_amount(0), Ok(0));
+        assert_eq!(signed_amount(i64::MAX as u64), Ok(i64::MAX));
+        assert_eq!(signed_amount(i64::MAX as u64 + 1), Err(ERROR_OVERFLOW));
+        assert_eq!(signed_amount(u64::MAX), Err(ERROR_OVERFLOW));
+    }
+
+    #[test]
+    fn cost_balance_bounds() {
+        let mut player = PlayerData::default();
+        set_titanium(&mut player, i64::MAX);
+        assert_eq!(player.cost_balance(-1), Err(ERROR_OVERFLOW));
+        assert_eq!(player.cost_balance(-i64::MAX), Err(ERROR_OVERFLOW));
+        assert_eq!(player.local.0[CONFIG.titanium()], i64::MAX);
+        assert_eq!(player.pending.titanium, 0);
+        assert_eq!(player.cost_balance(i64::MAX), Ok(()));
+        assert_eq!(player.local.0[CONFIG.titanium()], 0);
+        assert_eq!(player.pending.titanium, -i64::MAX);
+        assert_eq!(player.cost_balance(1), Err(ERROR_NOT_ENOUGH_BALANCE));
+        assert_eq!(player.cost_balance(0), Ok(()));
+        assert_eq!(player.cost_balance(-i64::MAX), Ok(()));
+        assert_eq!(player.local.0[CONFIG.titanium()], i64::MAX);
+        assert_eq!(player.pending.titanium, 0);
+    }
+
+    #[test]
+    fn add_resource_bounds() {
+        let mut player = PlayerData::default();
+        assert_eq!(player.add_resource(LOCAL_ATTRIBUTES_SIZE, 1), Err(ERROR_INDEX_OUT_OF_BOUND));
+        assert_eq!(player.add_resource(0, i64::MAX as u64 + 1), Err(ERROR_OVERFLOW));
+        player.local.0[0] = 0;
+        assert_eq!(player.add_resource(0, i64::MAX as u64), Ok(()));
+        assert_eq!(player.local.0[0], i64::MAX);
+        assert_eq!(player.add_resource(0, 1), Err(ERROR_OVERFLOW));
+        assert_eq!(player.local.0[0], i64::MAX);
+        assert_eq!(player.add_resource(0, 0), Ok(()));
+        assert_eq!(player.pending.titanium, 0);
+        set_titanium(&mut player, 0);
+        assert_eq!(player.add_resource(CONFIG.titanium(), 5), Ok(()));
+        assert_eq!(player.local.0[CONFIG.titanium()], 5);
+        assert_eq!(player.pending.titanium, 5);
+    }
+
+    #[test]
+    fn receive_resource_bounds() {
+        let mut player = PlayerData::default();
+        player.stats.received = u64::MAX - 1;
+        assert_eq!(player.receive_resource(0, 5), Ok(()));
+        assert_eq!(player.stats.received, u64::MAX);
+        player.local.0[0] = i64::MAX;
+        assert_eq!(player.receive_resource(0, 1), Err(ERROR_OVERFLOW));
+        assert_eq!(player.stats.received, u64::MAX);
+        assert_eq!(player.receive_resource(LOCAL_ATTRIBUTES_SIZE, 1), Err(ERROR_INDEX_OUT_OF_BOUND));
+    }
+
+    #[test]
+    fn withdraw_bounds() {
+        let mut player = PlayerData::default();
+        set_titanium(&mut player, i64::MAX);
+        player.stats.withdrawn = u64::MAX - 1;
+        assert_eq!(player.withdraw(i64::MAX as u64 + 1), Err(ERROR_OVERFLOW));
+        assert_eq!(player.withdraw(i64::MAX as u64), Ok(()));
+        assert_eq!(player.local.0[CONFIG.titanium()], 0);
+        assert_eq!(player.stats.withdrawn, u64::MAX);
+        assert_eq!(player.withdraw(1), Err(ERROR_NOT_ENOUGH_BALANCE));
+        assert_eq!(player.withdraw(0), Ok(()));
+        assert_eq!(player.stats.withdrawn, u64::MAX);
+    }
+
+    #[test]
+    fn redeem_bounds() {
+        let mut player = PlayerData::default();
+        let bounty = CONFIG.resource(0).unwrap().bounty.unwrap();
+        player.redeem_info[0] = u8::MAX;
+        assert!(player.next_bounty(0, 0).is_none());
+        assert_eq!(player.redeem_bounty(0, 0), Err(ERROR_BOUNTY_LIMIT));
+        // the count starts over in the next period
+        let counter = CONFIG.bounty_reset_period();
+        assert_eq!(player.next_bounty(0, counter).unwrap().remaining, bounty.cap);
+
+        player.redeem_info[0] = bounty.cap - 1;
+        player.local.0[0] = bounty.cost(bounty.cap - 1).unwrap() as i64;
+        set_titanium(&mut player, 0);
+        player.season_score = u64::MAX;
+        player.stats.bounty_reward = u64::MAX;
+        assert_eq!(player.next_bounty(0, 0).unwrap().remaining, 1);
+        assert_eq!(player.redeem_bounty(0, 0), bounty.reward(bounty.cap - 1));
+        assert_eq!(player.redeem_info[0], bounty.cap);
+        assert_eq!(player.local.0[0], 0);
+        assert_eq!(player.season_score, u64::MAX);
+        assert_eq!(player.stats.bounty_reward, u64::MAX);
+        assert_eq!(player.redeem_bounty(0, 0), Err(ERROR_BOUNTY_LIMIT));
+        assert_eq!(player.redeem_bounty(CONFIG.titanium(), 0), Err(ERROR_BOUNTY_NOT_ELIGIBLE));
+        assert_eq!(player.redeem_bounty(LOCAL_ATTRIBUTES_SIZE, 0), Err(ERROR_INDEX_OUT_OF_BOUND));
+    }
+
+    #[test]
+    fn object_class_follows_upgrades() {
+        let mut player = PlayerData::default();
+        player.objects.push(Object::new([0; 8]));
+        assert_eq!(player.objects[0].class(), 0);
+        player.upgrade_object(0, 1);
+        assert_eq!(player.objects[0].class(), 1);
+        player.upgrade_object(0, 3);
+        player.upgrade_object(0, 3);
+        assert_eq!(player.objects[0].class(), 3);
+    }
+
+    #[test]
+    fn inc_exp_bounds() {
+        let mut player = PlayerData::default();
+        player.exp = 98;
+        player.inc_exp();
+        assert_eq!((player.level, player.exp), (1, 99));
+        player.inc_exp();
+        assert_eq!((player.level, player.exp), (2, 0));
+
+        player.level = u16::MAX - 1;
+        player.exp = 99;
+        player.inc_exp();
+        assert_eq!((player.level, player.exp), (u16::MAX, 0));
+        player.exp = 99;
+        player.inc_exp();
+        assert_eq!((player.level, player.exp), (u16::MAX, 99));
+    }
+
+    #[test]
+    fn collect_energy_bounds() {
+        let ticks = CONFIG.energy_regen_ticks();
+        let cost = CONFIG.energy_collect_cost();
+
+        let mut player = PlayerData::default();
+        player.energy = 0;
+        if cost > 0 {
+            set_titanium(&mut player, cost as i64 - 1);
+            assert_eq!(player.collect_energy(ticks * 10, 100), Err(ERROR_NOT_ENOUGH_BALANCE));
+        }
+
+        // an empty supply pays nothing and keeps the ticks pending
+        set_titanium(&mut player, cost as i64 * 3);
+        assert_eq!(player.collect_energy(ticks * 10, 0), Ok(()));
+        assert_eq!((player.energy, player.last_check_point), (0, 0));
+
+        // a short supply pays part of the pending energy
+        assert_eq!(player.collect_energy(ticks * 10, 4), Ok(()));
+        assert_eq!(player.energy, 4);
+        assert_eq!(player.last_check_point as u64, ticks * 4);
+        assert_eq!(player.pending.energy, 4);
+
+        // the energy stops at max_energy and the pending ticks are dropped
+        let max_energy = CONFIG.max_energy(player.level);
+        let counter = ticks * (max_energy + 10);
+        assert_eq!(player.collect_energy(counter, u64::MAX), Ok(()));
+        assert_eq!(player.energy as u64, max_energy);
+        assert_eq!(player.last_check_point as u64, counter);
+        assert_eq!(player.stats.titanium_spent, cost * 3);
+
+        // max_energy of the top level still fits the u16 energy
+        let mut player = PlayerData::default();
+        player.level = u16::MAX;
+        player.energy = 0;
+        set_titanium(&mut player, cost as i64);
+        player.stats.titanium_spent = u64::MAX;
+        assert_eq!(player.collect_energy(ticks, u64::MAX), Ok(()));
+        assert_eq!(player.energy as u64, CONFIG.max_energy(u16::MAX));
+        assert_eq!(player.stats.titanium_spent, u64::MAX);
+    }
+}
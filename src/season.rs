@@ -0,0 +1,115 @@
+use crate::StorageData;
+use crate::MERKLE_MAP;
+use serde::Serialize;
+use std::slice::IterMut;
+
+const SEASON_RECORD_KEY: u64 = 0xff04;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Rank {
+    pub pid: [u64; 2],
+    pub score: u64,
+}
+
+impl StorageData for Rank {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        Rank {
+            pid: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            score: *u64data.next().unwrap(),
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.pid[0]);
+        data.push(self.pid[1]);
+        data.push(self.score);
+    }
+}
+
+/// Keep the ranking sorted by score (highest first) and at most size long.
+pub fn update_ranking(ranking: &mut Vec<Rank>, pid: &[u64; 2], score: u64, size: usize) {
+    ranking.retain(|r| r.pid != *pid);
+    let index = ranking.iter().position(|r| r.score < score).unwrap_or(ranking.len());
+    if index < size {
+        ranking.insert(index, Rank { pid: *pid, score });
+        ranking.truncate(size);
+    }
+}
+
+/* The final ranking of a season
+ *
+ * Written once when the season rolls over. The player at rank i can claim
+ * rewards[i] through a settlement, bit i of claimed is set once it did.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct SeasonRecord {
+    pub season: u32,
+    pub ended_at: u64,
+    pub ranking: Vec<Rank>,
+    pub rewards: Vec<u64>,
+    pub claimed: u64,
+}
+
+impl SeasonRecord {
+    fn to_key(season: u32) -> [u64; 4] {
+        [season as u64, 0, SEASON_RECORD_KEY, 0]
+    }
+
+    /// Rank and reward of a player that has not claimed yet.
+    pub fn unclaimed(&self, pid: &[u64; 2]) -> Option<(usize, u64)> {
+        let index = self.ranking.iter().position(|r| r.pid == *pid)?;
+        let reward = *self.rewards.get(index)?;
+        if self.claimed & (1 << index) != 0 || reward == 0 {
+            None
+        } else {
+            Some((index, reward))
+        }
+    }
+
+    pub fn get(season: u32) -> Option<Self> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&Self::to_key(season));
+        if data.is_empty() {
+            None
+        } else {
+            let mut u64data = data.iter_mut();
+            Some(Self::from_data(&mut u64data))
+        }
+    }
+
+    pub fn store(&self) {
+        let mut data = Vec::new();
+        self.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&Self::to_key(self.season), data.as_slice());
+    }
+}
+
+impl StorageData for SeasonRecord {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let season = *u64data.next().unwrap() as u32;
+        let ended_at = *u64data.next().unwrap();
+        let claimed = *u64data.next().unwrap();
+        let size = *u64data.next().unwrap();
+        let ranking = (0..size).map(|_| Rank::from_data(u64data)).collect();
+        let rewards = (0..size).map(|_| *u64data.next().unwrap()).collect();
+        SeasonRecord {
+            season,
+            ended_at,
+            ranking,
+            rewards,
+            claimed,
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.season as u64);
+        data.push(self.ended_at);
+        data.push(self.claimed);
+        data.push(self.ranking.len() as u64);
+        for r in self.ranking.iter() {
+            r.to_data(data);
+        }
+        for i in 0..self.ranking.len() {
+            data.push(self.rewards.get(i).map_or(0, |x| *x));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::config::CONFIG;
+use crate::StorageData;
+use serde::Serialize;
+use std::slice::IterMut;
+
+/* Titanium locked by a player
+ *
+ * lock indexes CONFIG.stake_locks, the stake can be taken back once the
+ * lock period is over and earns the lock rate for every tick it was staked.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct Stake {
+    pub amount: u64,
+    pub lock: u32,
+    pub start: u32, // counter when the titanium was staked
+}
+
+impl Stake {
+    pub fn unlock_at(&self) -> u64 {
+        self.start as u64 + CONFIG.stake_lock(self.lock as usize).ticks
+    }
+
+    /// Yield earned until counter, paid from the stake reward pool.
+    pub fn reward(&self, counter: u64) -> u64 {
+        let rate = CONFIG.stake_lock(self.lock as usize).rate;
+        let elapsed = counter.saturating_sub(self.start as u64);
+        let reward = self.amount as u128 * elapsed as u128 * rate as u128
+            / (10000 * CONFIG.ticks_per_day() as u128);
+        reward.min(u64::MAX as u128) as u64
+    }
+}
+
+impl StorageData for Stake {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let amount = *u64data.next().unwrap();
+        let info = *u64data.next().unwrap();
+        Stake {
+            amount,
+            lock: (info >> 32) as u32,
+            start: (info & 0xffffffff) as u32,
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.amount);
+        data.push(((self.lock as u64) << 32) + self.start as u64);
+    }
+}
@@ -1,10 +1,21 @@
+use crate::amm::{pool_id, Pool};
+use crate::board::BountyBoard;
+use crate::config::SEASON_RANKS;
 use crate::config::ADMIN_PUBKEY;
 use crate::config::CONFIG;
+use crate::config::LOCAL_ATTRIBUTES_SIZE;
+use crate::cost::*;
 use crate::error::*;
-use crate::events::Event;
+use crate::events::{Event, EVENT_LISTING_EXPIRE, EVENT_OBJECT, TICK_PENDING};
+use crate::leaderboard::{Leaderboard, LEADERBOARD_OBJECTS};
+use crate::ledger::Ledger;
+use crate::market::CardListing;
 use crate::object::Object;
-use crate::player::AutomataPlayer;
+use crate::player::{signed_amount, AutomataPlayer, Pending, PlayerData};
+use crate::season::{update_ranking, Rank, SeasonRecord};
 use crate::player::Owner;
+use crate::trade::CardOffer;
+use serde::Serialize;
 use std::cell::RefCell;
 use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_abi::WithdrawInfo;
@@ -38,10 +49,32 @@ pub enum Command {
     InstallObject(InstallObject),
     RestartObject(RestartObject),
     InstallCard(InstallCard),
+    CraftCard(CraftCard),
+    RerollCard(RerollCard),
+    RecycleCard(RecycleCard),
+    FuseCards(FuseCards),
+    OfferCard(OfferCard),
+    AcceptCardOffer(AcceptCardOffer),
+    CancelCardOffer(CancelCardOffer),
+    ListCard(ListCard),
+    BuyListing(BuyListing),
+    CancelListing(CancelListing),
+    Swap(Swap),
+    AddLiquidity(AddLiquidity),
+    RemoveLiquidity(RemoveLiquidity),
+    Transfer(Transfer),
+    RefillEnergy(RefillEnergy),
     Withdraw(Withdraw),
     Deposit(Deposit),
     Bounty(Bounty),
     InstallPlayer,
+    FulfillBounty(FulfillBounty),
+    ClaimSeasonReward(ClaimSeasonReward),
+    Stake(Stake),
+    Airdrop(Airdrop),
+    FundStakeRewards(FundStakeRewards),
+    SeedLedger(SeedLedger),
+    Unstake(Unstake),
     CollectEnergy,
     Tick,
 }
@@ -50,6 +83,14 @@ trait CommandHandler {
     fn handle(&self, pid: &[u64; 2], nonce: u64, rand: &[u64; 4]) -> Result<(), u32>;
 }
 
+/// Load a player for a command, players move into a new season with the
+/// first command that touches them in it.
+fn get_player(pid: &[u64; 2]) -> Option<AutomataPlayer> {
+    let mut player = AutomataPlayer::get_from_pid(pid)?;
+    player.data.enter_season(CONFIG.season(STATE.0.borrow().queue.counter));
+    Some(player)
+}
+
 #[derive (Clone)]
 pub struct UpgradeObject {
     object_index: usize,
@@ -58,13 +99,16 @@ pub struct UpgradeObject {
 
 impl CommandHandler for UpgradeObject {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = get_player(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_ALREADY_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                player.data.pay_cost()?;
+                let counter = STATE.0.borrow().queue.counter;
+                let supply = STATE.0.borrow().supplier;
+                player.data.pay_cost(COST_UPGRADE_OBJECT, counter, supply)?;
                 player.data.upgrade_object(self.object_index, self.feature_index);
+                STATE.0.borrow_mut().settle(&mut player.data)?;
                 player.store();
                 Ok(())
             }
@@ -80,22 +124,26 @@ pub struct InstallObject {
 
 impl CommandHandler for InstallObject {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = get_player(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
                 let objindex = player.data.objects.len();
                 unsafe { require(objindex == self.object_index) };
-                player.data.pay_cost()?;
+                let counter = STATE.0.borrow().queue.counter;
+                let supply = STATE.0.borrow().supplier;
+                player.data.pay_cost(COST_INSTALL_OBJECT, counter, supply)?;
                 let cards = self.modifiers;
                 let mut object = Object::new(cards);
-                let counter = STATE.0.borrow().queue.counter;
                 object.start_new_modifier(0, counter);
                 let delay = player.data.cards[object.cards[0] as usize].duration;
                 player.data.objects.push(object);
+                STATE.0.borrow_mut().settle(&mut player.data)?;
                 player.store();
+                Leaderboard::record(LEADERBOARD_OBJECTS, pid, player.data.objects.len() as u64);
                 STATE.0.borrow_mut().queue.insert(Event {
+                    kind: EVENT_OBJECT,
                     object_index: self.object_index ,
                     owner: *pid,
                     delta: delay as usize,
@@ -114,24 +162,27 @@ pub struct RestartObject {
 
 impl CommandHandler for RestartObject {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = get_player(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_ALREADY_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                player.data.pay_cost()?;
                 let counter = STATE.0.borrow().queue.counter;
+                let supply = STATE.0.borrow().supplier;
+                player.data.pay_cost(COST_RESTART_OBJECT, counter, supply)?;
                 if let Some(delay) = player.data.restart_object_card(
                     self.object_index,
                     self.modifiers,
                     counter,
                 ) {
                     STATE.0.borrow_mut().queue.insert(Event {
+                        kind: EVENT_OBJECT,
                         object_index: self.object_index,
                         owner: *pid,
                         delta: delay,
                     });
                 }
+                STATE.0.borrow_mut().settle(&mut player.data)?;
                 player.store();
                 Ok(())
             }
@@ -146,13 +197,207 @@ pub struct InstallCard {
 
 impl CommandHandler for InstallCard {
     fn handle(&self, pid: &[u64; 2], nonce: u64, rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let counter = STATE.0.borrow().queue.counter;
+                let supply = STATE.0.borrow().supplier;
+                let paid = player.data.pay_cost(COST_CARD, counter, supply)?;
+                player.data.generate_card(rand, paid);
+                STATE.0.borrow_mut().settle(&mut player.data)?;
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct CraftCard {
+    outputs: [usize; 2],
+}
+
+impl CommandHandler for CraftCard {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                player.data.validate_craft(self.outputs)?;
+                let counter = STATE.0.borrow().queue.counter;
+                let supply = STATE.0.borrow().supplier;
+                let paid = player.data.pay_cost(COST_CARD, counter, supply)?;
+                player.data.craft_card(self.outputs, rand, paid)?;
+                STATE.0.borrow_mut().settle(&mut player.data)?;
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct RerollCard {
+    card_index: usize,
+}
+
+impl CommandHandler for RerollCard {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let counter = STATE.0.borrow().queue.counter;
+                player.data.reroll_card(self.card_index, rand, counter)?;
+                STATE.0.borrow_mut().settle(&mut player.data)?;
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct RecycleCard {
+    card_index: usize,
+}
+
+impl CommandHandler for RecycleCard {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                player.data.recycle_card(self.card_index)?;
+                STATE.0.borrow_mut().settle(&mut player.data)?;
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct FuseCards {
+    card_indexes: [usize; 2],
+}
+
+impl CommandHandler for FuseCards {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let [index_a, index_b] = self.card_indexes;
+                player.data.validate_fusion(index_a, index_b)?;
+                let counter = STATE.0.borrow().queue.counter;
+                let supply = STATE.0.borrow().supplier;
+                let paid = player.data.pay_cost(COST_CARD, counter, supply)?;
+                player.data.fuse_cards(index_a, index_b, paid)?;
+                STATE.0.borrow_mut().settle(&mut player.data)?;
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct OfferCard {
+    card_index: usize,
+    price: u64,
+    buyer: [u64; 2],
+}
+
+impl CommandHandler for OfferCard {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                if self.price == 0 {
+                    return Err(ERROR_INVALID_PRICE);
+                }
+                if self.buyer == *pid {
+                    return Err(ERROR_OFFER_FORBIDDEN);
+                }
+                let card = player.data.remove_card(self.card_index)?;
+                let offer_id = STATE.0.borrow().offer_counter;
+                STATE.0.borrow_mut().offer_counter += 1;
+                let offer = CardOffer {
+                    seller: *pid,
+                    buyer: self.buyer,
+                    price: self.price,
+                    card,
+                };
+                offer.store(offer_id);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct AcceptCardOffer {
+    offer_id: u64,
+}
+
+impl CommandHandler for AcceptCardOffer {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let offer = CardOffer::get(self.offer_id).ok_or(ERROR_OFFER_NOT_EXIST)?;
+                if offer.seller == *pid || !offer.is_open_to(pid) {
+                    return Err(ERROR_OFFER_FORBIDDEN);
+                }
+                player.data.cost_balance(signed_amount(offer.price)?)?;
+                let mut seller = get_player(&offer.seller).unwrap();
+                seller.data.cost_balance(-signed_amount(offer.price)?)?;
+                player.data.cards.push(offer.card);
+                STATE.0.borrow_mut().settle_both(&mut player.data, &mut seller.data)?;
+                CardOffer::remove(self.offer_id);
+                seller.store();
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct CancelCardOffer {
+    offer_id: u64,
+}
+
+impl CommandHandler for CancelCardOffer {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                player.data.pay_cost()?;
-                player.data.generate_card(rand);
+                let offer = CardOffer::get(self.offer_id).ok_or(ERROR_OFFER_NOT_EXIST)?;
+                if offer.seller != *pid {
+                    return Err(ERROR_OFFER_FORBIDDEN);
+                }
+                player.data.cards.push(offer.card);
+                CardOffer::remove(self.offer_id);
                 player.store();
                 Ok(())
             }
@@ -161,6 +406,335 @@ impl CommandHandler for InstallCard {
 }
 
 
+#[derive (Clone)]
+pub struct ListCard {
+    card_index: usize,
+    price: u64,
+}
+
+impl CommandHandler for ListCard {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                if self.price == 0 {
+                    return Err(ERROR_INVALID_PRICE);
+                }
+                let card = player.data.remove_card(self.card_index)?;
+                let duration = CONFIG.market_listing_duration();
+                let listing_id = STATE.0.borrow().listing_counter;
+                STATE.0.borrow_mut().listing_counter += 1;
+                let counter = STATE.0.borrow().queue.counter;
+                let listing = CardListing {
+                    listing_id,
+                    seller: *pid,
+                    price: self.price,
+                    expire_at: counter + duration,
+                    card,
+                };
+                listing.open();
+                player.store();
+                STATE.0.borrow_mut().queue.insert(Event {
+                    kind: EVENT_LISTING_EXPIRE,
+                    object_index: 0,
+                    owner: [listing_id, 0],
+                    delta: duration as usize,
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct BuyListing {
+    listing_id: u64,
+}
+
+impl CommandHandler for BuyListing {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let listing = CardListing::get(self.listing_id).ok_or(ERROR_LISTING_NOT_EXIST)?;
+                if listing.seller == *pid {
+                    return Err(ERROR_OFFER_FORBIDDEN);
+                }
+                player.data.cost_balance(signed_amount(listing.price)?)?;
+                let fee = CONFIG.market_fee(listing.price);
+                let mut seller = get_player(&listing.seller).unwrap();
+                seller.data.cost_balance(-signed_amount(listing.price - fee)?)?;
+                player.data.cards.push(listing.card);
+                player.data.pending.sink(fee);
+                STATE.0.borrow_mut().settle_both(&mut player.data, &mut seller.data)?;
+                CardListing::close(self.listing_id);
+                seller.store();
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct CancelListing {
+    listing_id: u64,
+}
+
+impl CommandHandler for CancelListing {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let listing = CardListing::get(self.listing_id).ok_or(ERROR_LISTING_NOT_EXIST)?;
+                if listing.seller != *pid {
+                    return Err(ERROR_OFFER_FORBIDDEN);
+                }
+                player.data.cards.push(listing.card);
+                CardListing::close(self.listing_id);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct Swap {
+    from: usize,
+    to: usize,
+    amount: u64,
+    min_out: u64,
+}
+
+impl CommandHandler for Swap {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let id = pool_id(self.from, self.to)?;
+                player.data.take_resource(self.from, self.amount)?;
+                // the pool is written back once the player got the output
+                let mut pool = STATE.0.borrow_mut().get_pool(id)
+                    .ok_or(ERROR_POOL_NOT_EXIST)?
+                    .clone();
+                let amount_out = pool.swap(self.from, self.amount, self.min_out)?;
+                player.data.add_resource(self.to, amount_out)?;
+                let mut state = STATE.0.borrow_mut();
+                state.settle(&mut player.data)?;
+                state.put_pool(pool);
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct AddLiquidity {
+    pair: [usize; 2],
+    amounts: [u64; 2],
+    min_shares: u64,
+}
+
+impl CommandHandler for AddLiquidity {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let id = pool_id(self.pair[0], self.pair[1])?;
+                player.data.take_resource(self.pair[0], self.amounts[0])?;
+                player.data.take_resource(self.pair[1], self.amounts[1])?;
+                // pool reserves follow the ordered pair
+                let amounts = if self.pair[0] < self.pair[1] {
+                    self.amounts
+                } else {
+                    [self.amounts[1], self.amounts[0]]
+                };
+                // the pool only exists once its first deposit succeeded
+                let mut pool = STATE.0.borrow_mut().get_pool(id)
+                    .map_or(Pool::new(id), |p| p.clone());
+                let minted = pool.add_liquidity(amounts, self.min_shares)?;
+                player.data.add_shares(id, minted)?;
+                let mut state = STATE.0.borrow_mut();
+                state.settle(&mut player.data)?;
+                state.put_pool(pool);
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct RemoveLiquidity {
+    pair: [usize; 2],
+    shares: u64,
+    min_amounts: [u64; 2],
+}
+
+impl CommandHandler for RemoveLiquidity {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let id = pool_id(self.pair[0], self.pair[1])?;
+                player.data.remove_shares(id, self.shares)?;
+                let mut pool = STATE.0.borrow_mut().get_pool(id)
+                    .ok_or(ERROR_POOL_NOT_EXIST)?
+                    .clone();
+                let amounts = pool.remove_liquidity(self.shares, self.min_amounts)?;
+                player.data.add_resource(pool.pair[0], amounts[0])?;
+                player.data.add_resource(pool.pair[1], amounts[1])?;
+                let mut state = STATE.0.borrow_mut();
+                state.settle(&mut player.data)?;
+                state.put_pool(pool);
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[derive (Clone)]
+pub struct Transfer {
+    resource: usize,
+    amount: u64,
+    target: [u64; 2],
+}
+
+impl CommandHandler for Transfer {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                if self.target == *pid {
+                    return Err(ERROR_INVALID_TRANSFER);
+                }
+                let mut target = get_player(&self.target).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+                let counter = STATE.0.borrow().queue.counter;
+                let delivered = player.data.send_resource(self.resource, self.amount, counter)?;
+                target.data.receive_resource(self.resource, delivered)?;
+                // the fee is burnt unless it is titanium which goes to the treasury
+                if self.resource == CONFIG.titanium() {
+                    player.data.pending.sink(self.amount - delivered);
+                }
+                STATE.0.borrow_mut().settle_both(&mut player.data, &mut target.data)?;
+                target.store();
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct FulfillBounty {
+    bounty_index: usize,
+}
+
+impl CommandHandler for FulfillBounty {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let mut state = STATE.0.borrow_mut();
+                let epoch = state.board.epoch;
+                let bounty = state
+                    .board
+                    .bounties
+                    .get(self.bounty_index)
+                    .ok_or(ERROR_INDEX_OUT_OF_BOUND)?;
+                player.data.fulfill_bounty(epoch, self.bounty_index, bounty)?;
+                state.settle(&mut player.data)?;
+                state.board.bounties[self.bounty_index].remaining -= 1;
+                state.record_score(pid, player.data.season_score);
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct Stake {
+    lock: usize,
+    amount: u64,
+}
+
+impl CommandHandler for Stake {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let counter = STATE.0.borrow().queue.counter;
+                let staked = STATE.0.borrow().staked.checked_add(self.amount).ok_or(ERROR_OVERFLOW)?;
+                player.data.stake(self.lock, self.amount, counter)?;
+                let mut state = STATE.0.borrow_mut();
+                state.settle(&mut player.data)?;
+                state.staked = staked;
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct Unstake {
+    stake_index: usize,
+}
+
+impl CommandHandler for Unstake {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let counter = STATE.0.borrow().queue.counter;
+                let stake = player.data.unstake(self.stake_index, counter)?;
+                // the yield is cut down to what the fees have funded so far
+                let reward = stake.reward(counter).min(STATE.0.borrow().stake_rewards);
+                let amount = stake.amount.checked_add(reward).ok_or(ERROR_OVERFLOW)?;
+                player.data.cost_balance(-signed_amount(amount)?)?;
+                let mut state = STATE.0.borrow_mut();
+                state.settle(&mut player.data)?;
+                state.stake_rewards -= reward;
+                state.staked -= stake.amount;
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive (Clone)]
 pub struct Bounty {
     bounty_index: usize,
@@ -168,27 +742,54 @@ pub struct Bounty {
 
 impl CommandHandler for Bounty {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = get_player(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_ALREADY_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                if let Some(v) = player.data.local.0.get(self.bounty_index) {
-                    let redeem_info = player.data.redeem_info[self.bounty_index];
-                    let cost = CONFIG.get_bounty_cost(redeem_info as u64);
-                    if *v > cost as i64 {
-                        player.data.local.0[self.bounty_index] = v - (cost as i64);
-                        player.data.redeem_info[self.bounty_index] += 1;
-                        let reward = CONFIG.get_bounty_reward(redeem_info as u64);
-                        player.data.cost_balance(-(reward as i64))?;
-                        player.store();
-                        Ok(())
-                    } else {
-                        Err(ERROR_NOT_ENOUGH_RESOURCE)
-                    }
-                } else {
-                    Err(ERROR_INDEX_OUT_OF_BOUND)
-                }
+                let counter = STATE.0.borrow().queue.counter;
+                player.data.redeem_bounty(self.bounty_index, counter)?;
+                let mut state = STATE.0.borrow_mut();
+                state.settle(&mut player.data)?;
+                state.record_score(pid, player.data.season_score);
+                drop(state);
+                player.store();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive (Clone)]
+pub struct ClaimSeasonReward {
+    season: u32,
+    data: [u64; 3], // withdraw info, the amount bits are replaced by the reward
+}
+
+impl CommandHandler for ClaimSeasonReward {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut player = get_player(pid);
+        match player.as_mut() {
+            None => Err(ERROR_PLAYER_NOT_EXIST),
+            Some(player) => {
+                player.check_and_inc_nonce(nonce);
+                let mut record = SeasonRecord::get(self.season).ok_or(ERROR_NO_SEASON_REWARD)?;
+                let (index, reward) = record.unclaimed(pid).ok_or(ERROR_NO_SEASON_REWARD)?;
+                let amount = reward.min(0xffffffff);
+                // season rewards are withdrawn from the treasury, they can
+                // not be claimed while it holds too little
+                player.data.pending.grant(amount);
+                player.data.pending.withdraw(amount);
+                STATE.0.borrow_mut().settle(&mut player.data)?;
+                record.claimed |= 1 << index;
+                let withdrawinfo = WithdrawInfo::new(
+                    &[(self.data[0] & !0xffffffff) + amount, self.data[1], self.data[2]],
+                    0,
+                );
+                SettlementInfo::append_settlement(withdrawinfo);
+                record.store();
+                player.store();
+                Ok(())
             }
         }
     }
@@ -205,15 +806,23 @@ impl CommandHandler for Deposit {
         //zkwasm_rust_sdk::dbg!("deposit\n");
         let mut admin = AutomataPlayer::get_from_pid(pid).unwrap();
         admin.check_and_inc_nonce(nonce);
-        let mut player = AutomataPlayer::get_from_pid(&[self.data[0], self.data[1]]);
+        let mut player = get_player(&[self.data[0], self.data[1]]);
         match player.as_mut() {
             None => {
                 let mut player = AutomataPlayer::new_from_pid([self.data[0], self.data[1]]);
-                player.data.cost_balance(-(self.data[2] as i64))?;
+                player.data.pending.new_player();
+                player.data.cost_balance(-signed_amount(self.data[2])?)?;
+                player.data.pending.deposit(self.data[2]);
+                let mut state = STATE.0.borrow_mut();
+                player.data.season = CONFIG.season(state.queue.counter);
+                state.settle(&mut player.data)?;
+                drop(state);
                 player.store();
             }
             Some(player) => {
-                player.data.cost_balance(-(self.data[2] as i64))?;
+                player.data.cost_balance(-signed_amount(self.data[2])?)?;
+                player.data.pending.deposit(self.data[2]);
+                STATE.0.borrow_mut().settle(&mut player.data)?;
                 player.store();
             }
         };
@@ -222,6 +831,97 @@ impl CommandHandler for Deposit {
     }
 }
 
+#[derive (Clone)]
+pub struct RefillEnergy {
+    amount: u64,
+}
+
+impl CommandHandler for RefillEnergy {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::get_from_pid(pid).unwrap();
+        admin.check_and_inc_nonce(nonce);
+        let mut state = STATE.0.borrow_mut();
+        state.supplier = state.supplier.checked_add(self.amount).ok_or(ERROR_OVERFLOW)?;
+        drop(state);
+        admin.store();
+        Ok(())
+    }
+}
+
+#[derive (Clone)]
+pub struct Airdrop {
+    target: [u64; 2],
+    amount: u64,
+}
+
+impl CommandHandler for Airdrop {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::get_from_pid(pid).unwrap();
+        admin.check_and_inc_nonce(nonce);
+        let mut target = get_player(&self.target).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        target.data.cost_balance(-signed_amount(self.amount)?)?;
+        target.data.pending.grant(self.amount);
+        STATE.0.borrow_mut().settle(&mut target.data)?;
+        target.store();
+        admin.store();
+        Ok(())
+    }
+}
+
+#[derive (Clone)]
+pub struct FundStakeRewards {
+    amount: u64,
+}
+
+impl CommandHandler for FundStakeRewards {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::get_from_pid(pid).unwrap();
+        admin.check_and_inc_nonce(nonce);
+        let mut state = STATE.0.borrow_mut();
+        let stake_rewards = state.stake_rewards.checked_add(self.amount).ok_or(ERROR_OVERFLOW)?;
+        state.ledger.allocate(self.amount)?;
+        state.stake_rewards = stake_rewards;
+        drop(state);
+        admin.store();
+        Ok(())
+    }
+}
+
+/// Titanium of a deployment that predates the ledger, counted once by the
+/// admin from the stored players.
+#[derive (Clone)]
+pub struct SeedLedger {
+    players: u64,
+    deposited: u64,
+    minted: u64,
+    withdrawn: u64,
+}
+
+impl CommandHandler for SeedLedger {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
+        let mut admin = AutomataPlayer::get_from_pid(pid).unwrap();
+        admin.check_and_inc_nonce(nonce);
+        let mut state = STATE.0.borrow_mut();
+        if state.ledger.seeded {
+            return Err(ERROR_LEDGER_SEEDED);
+        }
+        let ledger = &mut state.ledger;
+        // players moved since the upgrade are already counted on top of zero
+        let players = ledger.players.wrapping_add(self.players);
+        let deposited = ledger.deposited.checked_add(self.deposited).ok_or(ERROR_OVERFLOW)?;
+        let minted = ledger.minted.checked_add(self.minted).ok_or(ERROR_OVERFLOW)?;
+        let withdrawn = ledger.withdrawn.checked_add(self.withdrawn).ok_or(ERROR_OVERFLOW)?;
+        ledger.players = players;
+        ledger.deposited = deposited;
+        ledger.minted = minted;
+        ledger.withdrawn = withdrawn;
+        ledger.seeded = true;
+        drop(state);
+        admin.store();
+        Ok(())
+    }
+}
+
 #[derive (Clone)]
 pub struct Withdraw {
     data: [u64; 3],
@@ -229,13 +929,14 @@ pub struct Withdraw {
 
 impl CommandHandler for Withdraw {
     fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4]) -> Result<(), u32> {
-        let mut player = AutomataPlayer::get_from_pid(pid);
+        let mut player = get_player(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
                 let amount = self.data[0] & 0xffffffff;
-                player.data.cost_balance(amount as i64)?;
+                player.data.withdraw(amount)?;
+                STATE.0.borrow_mut().settle(&mut player.data)?;
                 let withdrawinfo =
                     WithdrawInfo::new(&[self.data[0], self.data[1], self.data[2]], 0);
                 SettlementInfo::append_settlement(withdrawinfo);
@@ -259,6 +960,28 @@ const WITHDRAW: u64 = 6;
 const DEPOSIT: u64 = 7;
 const BOUNTY: u64 = 8;
 const COLLECT_ENERGY: u64 = 9;
+const RECYCLE_CARD: u64 = 10;
+const FUSE_CARDS: u64 = 11;
+const OFFER_CARD: u64 = 12;
+const ACCEPT_CARD_OFFER: u64 = 13;
+const CANCEL_CARD_OFFER: u64 = 14;
+const LIST_CARD: u64 = 15;
+const BUY_LISTING: u64 = 16;
+const CANCEL_LISTING: u64 = 17;
+const CRAFT_CARD: u64 = 18;
+const REROLL_CARD: u64 = 19;
+const SWAP: u64 = 20;
+const ADD_LIQUIDITY: u64 = 21;
+const REMOVE_LIQUIDITY: u64 = 22;
+const TRANSFER: u64 = 23;
+const REFILL_ENERGY: u64 = 24;
+const FULFILL_BOUNTY: u64 = 25;
+const CLAIM_SEASON_REWARD: u64 = 26;
+const STAKE: u64 = 27;
+const UNSTAKE: u64 = 28;
+const AIRDROP: u64 = 29;
+const FUND_STAKE_REWARDS: u64 = 30;
+const SEED_LEDGER: u64 = 31;
 
 impl Transaction {
     pub fn decode_error(e: u32) -> &'static str {
@@ -268,6 +991,26 @@ impl Transaction {
             ERROR_NOT_ENOUGH_BALANCE => "NotEnoughBalance",
             ERROR_INDEX_OUT_OF_BOUND => "IndexOutofBound",
             ERROR_NOT_ENOUGH_RESOURCE => "NotEnoughResource",
+            ERROR_CARD_IN_USE => "CardInUse",
+            ERROR_INVALID_FUSION => "InvalidFusion",
+            ERROR_OFFER_NOT_EXIST => "OfferNotExist",
+            ERROR_OFFER_FORBIDDEN => "OfferForbidden",
+            ERROR_LISTING_NOT_EXIST => "ListingNotExist",
+            ERROR_BOUNTY_NOT_ELIGIBLE => "BountyNotEligible",
+            ERROR_SLIPPAGE => "Slippage",
+            ERROR_POOL_NOT_EXIST => "PoolNotExist",
+            ERROR_NOT_ENOUGH_LIQUIDITY => "NotEnoughLiquidity",
+            ERROR_INVALID_TRANSFER => "InvalidTransfer",
+            ERROR_TRANSFER_LIMIT => "TransferLimit",
+            ERROR_OVERFLOW => "Overflow",
+            ERROR_BOUNTY_LIMIT => "BountyLimit",
+            ERROR_BOUNTY_CLAIMED => "BountyClaimed",
+            ERROR_NO_SEASON_REWARD => "NoSeasonReward",
+            ERROR_STAKE_LOCKED => "StakeLocked",
+            ERROR_TOO_MANY_STAKES => "TooManyStakes",
+            ERROR_LEDGER_SEEDED => "LedgerSeeded",
+            ERROR_INVALID_PRICE => "InvalidPrice",
+            ERROR_INVALID_CRAFT => "InvalidCraft",
             _ => "Unknown",
         }
     }
@@ -306,6 +1049,114 @@ impl Transaction {
             })
         } else if cmd == INSTALL_CARD {
             Command::InstallCard (InstallCard {})
+        } else if cmd == CRAFT_CARD {
+            Command::CraftCard (CraftCard {
+                outputs: [params[1] as usize, params[2] as usize]
+            })
+        } else if cmd == REROLL_CARD {
+            Command::RerollCard (RerollCard {
+                card_index: params[1] as usize
+            })
+        } else if cmd == FUSE_CARDS {
+            Command::FuseCards (FuseCards {
+                card_indexes: [params[1] as usize, params[2] as usize]
+            })
+        } else if cmd == OFFER_CARD {
+            Command::OfferCard (OfferCard {
+                card_index: params[1] as usize,
+                price: params[2],
+                buyer: [params[3], params[4]],
+            })
+        } else if cmd == ACCEPT_CARD_OFFER {
+            Command::AcceptCardOffer (AcceptCardOffer {
+                offer_id: params[1]
+            })
+        } else if cmd == CANCEL_CARD_OFFER {
+            Command::CancelCardOffer (CancelCardOffer {
+                offer_id: params[1]
+            })
+        } else if cmd == LIST_CARD {
+            Command::ListCard (ListCard {
+                card_index: params[1] as usize,
+                price: params[2],
+            })
+        } else if cmd == BUY_LISTING {
+            Command::BuyListing (BuyListing {
+                listing_id: params[1]
+            })
+        } else if cmd == CANCEL_LISTING {
+            Command::CancelListing (CancelListing {
+                listing_id: params[1]
+            })
+        } else if cmd == SWAP {
+            // params[1]: from + (to << 8)
+            Command::Swap (Swap {
+                from: (params[1] & 0xff) as usize,
+                to: ((params[1] >> 8) & 0xff) as usize,
+                amount: params[2],
+                min_out: params[3],
+            })
+        } else if cmd == ADD_LIQUIDITY {
+            Command::AddLiquidity (AddLiquidity {
+                pair: [(params[1] & 0xff) as usize, ((params[1] >> 8) & 0xff) as usize],
+                amounts: [params[2], params[3]],
+                min_shares: params[4],
+            })
+        } else if cmd == REMOVE_LIQUIDITY {
+            // min_amounts are given in the order of the ordered pair
+            Command::RemoveLiquidity (RemoveLiquidity {
+                pair: [(params[1] & 0xff) as usize, ((params[1] >> 8) & 0xff) as usize],
+                shares: params[2],
+                min_amounts: [params[3], params[4]],
+            })
+        } else if cmd == TRANSFER {
+            Command::Transfer (Transfer {
+                resource: params[1] as usize,
+                amount: params[2],
+                target: [params[3], params[4]],
+            })
+        } else if cmd == REFILL_ENERGY {
+            Command::RefillEnergy (RefillEnergy {
+                amount: params[1]
+            })
+        } else if cmd == FULFILL_BOUNTY {
+            Command::FulfillBounty (FulfillBounty {
+                bounty_index: params[1] as usize
+            })
+        } else if cmd == CLAIM_SEASON_REWARD {
+            Command::ClaimSeasonReward (ClaimSeasonReward {
+                season: params[1] as u32,
+                data: [params[2], params[3], params[4]],
+            })
+        } else if cmd == STAKE {
+            Command::Stake (Stake {
+                lock: params[1] as usize,
+                amount: params[2],
+            })
+        } else if cmd == UNSTAKE {
+            Command::Unstake (Unstake {
+                stake_index: params[1] as usize
+            })
+        } else if cmd == AIRDROP {
+            Command::Airdrop (Airdrop {
+                target: [params[1], params[2]],
+                amount: params[3],
+            })
+        } else if cmd == FUND_STAKE_REWARDS {
+            Command::FundStakeRewards (FundStakeRewards {
+                amount: params[1]
+            })
+        } else if cmd == SEED_LEDGER {
+            Command::SeedLedger (SeedLedger {
+                players: params[1],
+                deposited: params[2],
+                minted: params[3],
+                withdrawn: params[4],
+            })
+        } else if cmd == RECYCLE_CARD {
+            Command::RecycleCard (RecycleCard {
+                card_index: params[1] as usize
+            })
         } else if cmd == INSTALL_PLAYER {
             Command::InstallPlayer
         } else if cmd == COLLECT_ENERGY {
@@ -325,7 +1176,12 @@ impl Transaction {
         match player {
             Some(_) => Err(ERROR_PLAYER_ALREADY_EXIST),
             None => {
-                let player = AutomataPlayer::new_from_pid(*pid);
+                let mut player = AutomataPlayer::new_from_pid(*pid);
+                player.data.pending.new_player();
+                let mut state = STATE.0.borrow_mut();
+                player.data.season = CONFIG.season(state.queue.counter);
+                state.settle(&mut player.data)?;
+                drop(state);
                 player.store();
                 Ok(())
             }
@@ -333,11 +1189,13 @@ impl Transaction {
 
     }
     pub fn collect_energy(pid: &[u64; 2]) -> Result<(), u32> {
-        let player = AutomataPlayer::get_from_pid(pid);
+        let player = get_player(pid);
         let counter = STATE.0.borrow().queue.counter;
+        let supply = STATE.0.borrow().supplier;
         match player {
             Some(mut player) => {
-                player.data.collect_energy(counter)?;
+                player.data.collect_energy(counter, supply)?;
+                STATE.0.borrow_mut().settle(&mut player.data)?;
                 player.store();
                 Ok(())
             }
@@ -364,6 +1222,34 @@ impl Transaction {
                 .map_or_else(|e| e, |_| 0),
             Command::InstallCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
                 .map_or_else(|e| e, |_| 0),
+            Command::CraftCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::RerollCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::RecycleCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::FuseCards(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::OfferCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::AcceptCardOffer(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::CancelCardOffer(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::ListCard(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::BuyListing(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::CancelListing(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::Swap(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::AddLiquidity(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::RemoveLiquidity(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::Transfer(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
             Command::Deposit(cmd) => {
                 zkwasm_rust_sdk::dbg!("perform deposit: {:?} {:?}\n", {*pkey}, {*ADMIN_PUBKEY});
                 unsafe { require(*pkey == *ADMIN_PUBKEY) };
@@ -372,13 +1258,47 @@ impl Transaction {
             },
             Command::Bounty(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
                 .map_or_else(|e| e, |_| 0),
+            Command::FulfillBounty(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::ClaimSeasonReward(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::Stake(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
+            Command::Unstake(cmd) => cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                .map_or_else(|e| e, |_| 0),
 
+            Command::Airdrop(cmd) => {
+                unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                    .map_or_else(|e| e, |_| 0)
+            }
+            Command::FundStakeRewards(cmd) => {
+                unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                    .map_or_else(|e| e, |_| 0)
+            }
+            Command::SeedLedger(cmd) => {
+                unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                    .map_or_else(|e| e, |_| 0)
+            }
+            Command::RefillEnergy(cmd) => {
+                unsafe { require(*pkey == *ADMIN_PUBKEY) };
+                cmd.handle(&AutomataPlayer::pkey_to_pid(pkey), self.nonce, rand)
+                    .map_or_else(|e| e, |_| 0)
+            }
             Command::Tick => {
                 zkwasm_rust_sdk::dbg!("admin {:?}\n", {*ADMIN_PUBKEY});
                 zkwasm_rust_sdk::dbg!("pkey {:?}\n", {*pkey});
                 unsafe { require(*pkey == *ADMIN_PUBKEY) };
                 STATE.0.borrow_mut().queue.tick();
-                0
+                let pending = std::mem::take(&mut *TICK_PENDING.0.borrow_mut());
+                // the events are stored already, an overflow is only reported
+                let settled = STATE.0.borrow_mut().apply_pending(pending);
+                STATE.0.borrow_mut().emit_energy();
+                STATE.0.borrow_mut().refresh_board(rand);
+                STATE.0.borrow_mut().rollover_season();
+                settled.map_or_else(|e| e, |_| 0)
             }
         };
         vec![b as u64]
@@ -392,9 +1312,32 @@ lazy_static::lazy_static! {
     pub static ref STATE: SafeState = SafeState (RefCell::new(State::new()));
 }
 
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    counter: u64,
+    supplier: u64, // global energy supply
+    ledger: &'a Ledger,
+    balanced: bool, // the titanium invariant, see Ledger
+    pools: &'a Vec<Pool>,
+    board: &'a BountyBoard,
+    season: u32,
+    ranking: &'a Vec<Rank>, // of the current season
+    staked: u64,
+    stake_rewards: u64,
+}
+
 pub struct State {
-    supplier: u64,
+    supplier: u64, // global energy pool drawn by pay_cost and collect_energy
     queue: EventQueue<Event>,
+    offer_counter: u64,
+    listing_counter: u64,
+    pools: Vec<Pool>,
+    board: BountyBoard,
+    season: u32,
+    ranking: Vec<Rank>,
+    staked: u64, // titanium locked in player stakes
+    stake_rewards: u64, // titanium funded by pay_cost to pay the stake yield
+    ledger: Ledger,
 }
 
 impl State {
@@ -402,15 +1345,153 @@ impl State {
         State {
             supplier: 1000,
             queue: EventQueue::new(),
+            offer_counter: 0,
+            listing_counter: 0,
+            pools: vec![],
+            board: BountyBoard::default(),
+            season: 0,
+            ranking: vec![],
+            staked: 0,
+            stake_rewards: 0,
+            ledger: Ledger {
+                seeded: true,
+                ..Ledger::default()
+            },
         }
     }
+    /// The global state shared by all players.
     pub fn snapshot() -> String {
-        let counter = STATE.0.borrow().queue.counter;
-        serde_json::to_string(&counter).unwrap()
+        let state = STATE.0.borrow();
+        let snapshot = Snapshot {
+            counter: state.queue.counter,
+            supplier: state.supplier,
+            ledger: &state.ledger,
+            balanced: state.titanium_balanced(),
+            pools: &state.pools,
+            board: &state.board,
+            season: state.season,
+            ranking: &state.ranking,
+            staked: state.staked,
+            stake_rewards: state.stake_rewards,
+        };
+        serde_json::to_string(&snapshot).unwrap()
+    }
+
+    /// Apply what a player command recorded in PlayerData::pending, called
+    /// once the command can not fail anymore.
+    pub fn settle(&mut self, data: &mut PlayerData) -> Result<(), u32> {
+        self.apply_pending(std::mem::take(&mut data.pending))
+    }
+
+    /// Settle the two players of a command at once, neither is applied if
+    /// the other can not be.
+    pub fn settle_both(&mut self, a: &mut PlayerData, b: &mut PlayerData) -> Result<(), u32> {
+        let mut pending = std::mem::take(&mut a.pending);
+        pending.merge(std::mem::take(&mut b.pending));
+        self.apply_pending(pending)
+    }
+
+    fn apply_pending(&mut self, pending: Pending) -> Result<(), u32> {
+        self.ledger.apply(&pending)?;
+        self.supplier = self.supplier.saturating_sub(pending.energy);
+        self.stake_rewards = self.stake_rewards.saturating_add(pending.stake_fee);
+        Ok(())
+    }
+
+    fn emit_energy(&mut self) {
+        let cap = CONFIG.energy_supply_cap();
+        if self.supplier < cap {
+            self.supplier = (self.supplier + CONFIG.energy_emission()).min(cap);
+        }
+    }
+    fn refresh_board(&mut self, rand: &[u64; 4]) {
+        let epoch = CONFIG.board_epoch(self.queue.counter);
+        if epoch != self.board.epoch || self.board.bounties.is_empty() {
+            self.board = BountyBoard::generate(epoch, rand);
+        }
+    }
+
+    fn record_score(&mut self, pid: &[u64; 2], score: u64) {
+        update_ranking(&mut self.ranking, pid, score, SEASON_RANKS);
+    }
+
+    /// Archive the ranking of the finished season, its rewards can be claimed
+    /// from then on.
+    fn rollover_season(&mut self) {
+        let season = CONFIG.season(self.queue.counter);
+        if season != self.season {
+            let record = SeasonRecord {
+                season: self.season,
+                ended_at: self.queue.counter,
+                ranking: std::mem::take(&mut self.ranking),
+                rewards: CONFIG.season_rewards().to_vec(),
+                claimed: 0,
+            };
+            record.store();
+            self.season = season;
+        }
     }
+
     pub fn get_state(pid: Vec<u64>) -> String {
         let player = AutomataPlayer::get(&pid.try_into().unwrap()).unwrap();
-        serde_json::to_string(&player).unwrap()
+        let counter = STATE.0.borrow().queue.counter;
+        let mut state = serde_json::to_value(&player).unwrap();
+        state["data"]["energy_estimate"] =
+            serde_json::to_value(player.data.energy_estimate(counter)).unwrap();
+        let next_costs: Vec<Option<u64>> = (0..COST_ACTIONS)
+            .map(|a| player.data.next_cost(a, counter).ok())
+            .collect();
+        state["data"]["next_costs"] = serde_json::to_value(next_costs).unwrap();
+        let next_bounties: Vec<_> = (0..LOCAL_ATTRIBUTES_SIZE)
+            .map(|i| player.data.next_bounty(i, counter))
+            .collect();
+        state["data"]["next_bounties"] = serde_json::to_value(next_bounties).unwrap();
+        serde_json::to_string(&state).unwrap()
+    }
+
+    /// Check the titanium invariant described in Ledger.
+    pub fn titanium_balanced(&self) -> bool {
+        let ledger = &self.ledger;
+        let pooled: u128 = self
+            .pools
+            .iter()
+            .filter_map(|p| p.pair.iter().position(|r| *r == CONFIG.titanium()).map(|i| p.reserves[i] as u128))
+            .sum();
+        let held = ledger.players as u128
+            + ledger.treasury as u128
+            + self.staked as u128
+            + self.stake_rewards as u128
+            + pooled
+            + ledger.withdrawn as u128;
+        held == ledger.deposited as u128 + ledger.minted as u128
+    }
+
+    pub fn get_pool(&mut self, id: u64) -> Option<&mut Pool> {
+        self.pools.iter_mut().find(|p| p.id() == id)
+    }
+
+    /// Write back a pool changed on a copy, adding it if it is new.
+    pub fn put_pool(&mut self, pool: Pool) {
+        match self.get_pool(pool.id()) {
+            Some(p) => *p = pool,
+            None => self.pools.push(pool),
+        }
+    }
+
+    pub fn get_listings() -> String {
+        serde_json::to_string(&CardListing::all()).unwrap()
+    }
+
+    pub fn get_bounty_board() -> String {
+        serde_json::to_string(&STATE.0.borrow().board).unwrap()
+    }
+
+    pub fn get_season(season: u32) -> String {
+        serde_json::to_string(&SeasonRecord::get(season)).unwrap()
+    }
+
+    pub fn get_leaderboard(kind: u64) -> String {
+        serde_json::to_string(&Leaderboard::get(kind)).unwrap()
     }
 
     pub fn preempt() -> bool {
@@ -435,6 +1516,26 @@ impl State {
         let mut v = Vec::with_capacity(state.queue.list.len() + 10);
         v.push(state.supplier);
         state.queue.to_data(&mut v);
+        v.push(state.offer_counter);
+        v.push(state.listing_counter);
+        v.push(state.ledger.treasury);
+        v.push(state.pools.len() as u64);
+        for pool in state.pools.iter() {
+            pool.to_data(&mut v);
+        }
+        state.board.to_data(&mut v);
+        v.push(state.season as u64);
+        v.push(state.ranking.len() as u64);
+        for rank in state.ranking.iter() {
+            rank.to_data(&mut v);
+        }
+        v.push(state.staked);
+        v.push(state.stake_rewards);
+        v.push(state.ledger.players);
+        v.push(state.ledger.deposited);
+        v.push(state.ledger.minted);
+        v.push(state.ledger.withdrawn);
+        v.push(state.ledger.seeded as u64);
         let kvpair = unsafe { &mut MERKLE_MAP };
         kvpair.set(&[0, 0, 0, 0], v.as_slice());
         state.queue.store();
@@ -449,6 +1550,23 @@ impl State {
             let mut data = data.iter_mut();
             state.supplier = *data.next().unwrap();
             state.queue = EventQueue::from_data(&mut data);
+            state.offer_counter = data.next().map_or(0, |x| *x);
+            state.listing_counter = data.next().map_or(0, |x| *x);
+            state.ledger.treasury = data.next().map_or(0, |x| *x);
+            let pools_size = data.next().map_or(0, |x| *x);
+            state.pools = (0..pools_size).map(|_| Pool::from_data(&mut data)).collect();
+            state.board = BountyBoard::from_data(&mut data);
+            state.season = data.next().map_or(0, |x| *x) as u32;
+            let ranking_size = data.next().map_or(0, |x| *x);
+            state.ranking = (0..ranking_size).map(|_| Rank::from_data(&mut data)).collect();
+            state.staked = data.next().map_or(0, |x| *x);
+            state.stake_rewards = data.next().map_or(0, |x| *x);
+            state.ledger.players = data.next().map_or(0, |x| *x);
+            state.ledger.deposited = data.next().map_or(0, |x| *x);
+            state.ledger.minted = data.next().map_or(0, |x| *x);
+            state.ledger.withdrawn = data.next().map_or(0, |x| *x);
+            // the players of a state stored before the ledger are not counted
+            state.ledger.seeded = data.next().map_or(false, |x| *x != 0);
         }
     }
 }
@@ -0,0 +1,78 @@
+use serde::Serialize;
+use std::slice::IterMut;
+use zkwasm_rest_abi::StorageData;
+
+/* Lifetime counters of a player
+ *
+ * produced/consumed are the summed resource units moved by applied cards,
+ * cycles counts completed card programs (the modifier index wraps to 0) and
+ * halts counts how often an object stopped for lack of resource or energy.
+ */
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PlayerStats {
+    pub produced: u64,
+    pub consumed: u64,
+    pub cycles: u32,
+    pub halts: u32,
+    pub titanium_spent: u64,
+    pub bounty_reward: u64,
+    pub withdrawn: u64,
+    pub sent: u64,
+    pub received: u64,
+    pub titanium_produced: u64,
+}
+
+impl PlayerStats {
+    pub fn record_delta(&mut self, delta: i64) {
+        if delta > 0 {
+            self.produced = self.produced.saturating_add(delta as u64);
+        } else {
+            self.consumed = self.consumed.saturating_add(delta.unsigned_abs());
+        }
+    }
+}
+
+impl StorageData for PlayerStats {
+    /* The stats are a length word followed by one word per stat, stats
+     * missing from the block start at zero. Leaves stored before the stats
+     * end before the length word and get all zeros.
+     */
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let size = u64data.next().map_or(0, |x| *x) as usize;
+        let mut words = [0u64; 10];
+        for i in 0..size {
+            let v = *u64data.next().unwrap();
+            if i < words.len() {
+                words[i] = v;
+            }
+        }
+        PlayerStats {
+            produced: words[0],
+            consumed: words[1],
+            cycles: words[2] as u32,
+            halts: words[3] as u32,
+            titanium_spent: words[4],
+            bounty_reward: words[5],
+            withdrawn: words[6],
+            sent: words[7],
+            received: words[8],
+            titanium_produced: words[9],
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        let words = [
+            self.produced,
+            self.consumed,
+            self.cycles as u64,
+            self.halts as u64,
+            self.titanium_spent,
+            self.bounty_reward,
+            self.withdrawn,
+            self.sent,
+            self.received,
+            self.titanium_produced,
+        ];
+        data.push(words.len() as u64);
+        data.extend_from_slice(&words);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::card::Card;
+use crate::StorageData;
+use crate::MERKLE_MAP;
+use serde::Serialize;
+use std::slice::IterMut;
+
+const CARD_OFFER_KEY: u64 = 0xff01;
+
+/* An escrowed card offer
+ *
+ * The card is removed from the seller when the offer is made and kept in
+ * the offer leaf until it is accepted (moved to the buyer) or cancelled
+ * (moved back to the seller). A zero buyer means anyone can accept.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct CardOffer {
+    pub seller: [u64; 2],
+    pub buyer: [u64; 2],
+    pub price: u64,
+    pub card: Card,
+}
+
+impl CardOffer {
+    fn to_key(offer_id: u64) -> [u64; 4] {
+        [offer_id, 0, CARD_OFFER_KEY, 0]
+    }
+
+    pub fn is_open_to(&self, pid: &[u64; 2]) -> bool {
+        self.buyer == [0, 0] || self.buyer == *pid
+    }
+
+    pub fn get(offer_id: u64) -> Option<Self> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&Self::to_key(offer_id));
+        if data.is_empty() {
+            None
+        } else {
+            let mut u64data = data.iter_mut();
+            Some(Self::from_data(&mut u64data))
+        }
+    }
+
+    pub fn store(&self, offer_id: u64) {
+        let mut data = Vec::new();
+        self.to_data(&mut data);
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&Self::to_key(offer_id), data.as_slice());
+    }
+
+    pub fn remove(offer_id: u64) {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.set(&Self::to_key(offer_id), &[]);
+    }
+}
+
+impl StorageData for CardOffer {
+    fn from_data(u64data: &mut IterMut<u64>) -> Self {
+        let seller = [*u64data.next().unwrap(), *u64data.next().unwrap()];
+        let buyer = [*u64data.next().unwrap(), *u64data.next().unwrap()];
+        let price = *u64data.next().unwrap();
+        let card = Card::from_data(u64data);
+        CardOffer {
+            seller,
+            buyer,
+            price,
+            card,
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.seller[0]);
+        data.push(self.seller[1]);
+        data.push(self.buyer[0]);
+        data.push(self.buyer[1]);
+        data.push(self.price);
+        self.card.to_data(data);
+    }
+}